@@ -0,0 +1,142 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use config::Config;
+use serde_derive::Deserialize;
+
+// what an alarm plays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wake {
+    // id of playlist
+    Playlist(i64),
+    Fm,
+}
+
+// [[alarms]] of Settings.toml
+#[derive(Deserialize)]
+struct Entry {
+    time: String,
+    playlist: Option<i64>,
+    fm: Option<bool>,
+    enabled: Option<bool>,
+}
+
+// start playing at a time of every day
+#[derive(Clone, Debug)]
+pub struct Alarm {
+    pub time: NaiveTime,
+    pub wake: Wake,
+    pub enabled: bool,
+    // alarm rings at the first time after it
+    since: NaiveDateTime,
+}
+
+impl Alarm {
+    pub fn new(time: NaiveTime, wake: Wake, enabled: bool, now: NaiveDateTime) -> Alarm {
+        Alarm {
+            time,
+            wake,
+            enabled,
+            since: now,
+        }
+    }
+
+    // alarms of config, wrong ones are logged and skipped
+    pub fn from_config(config: &Config, now: NaiveDateTime) -> Vec<Alarm> {
+        let entries = match config.get::<Vec<Entry>>("alarms") {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries
+            .into_iter()
+            .filter_map(|entry| match Alarm::parse(entry, now) {
+                Ok(alarm) => Some(alarm),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn parse(entry: Entry, now: NaiveDateTime) -> Result<Alarm, failure::Error> {
+        let time = NaiveTime::parse_from_str(entry.time.trim(), "%H:%M")
+            .map_err(|_| format_err!("invalid alarm time {}", entry.time))?;
+        let wake = match (entry.playlist, entry.fm) {
+            (Some(id), _) => Wake::Playlist(id),
+            (None, Some(true)) => Wake::Fm,
+            _ => return Err(format_err!("alarm {} has no playlist or fm", entry.time)),
+        };
+        Ok(Alarm::new(time, wake, entry.enabled.unwrap_or(true), now))
+    }
+
+    // next time to ring
+    pub fn next(&self) -> NaiveDateTime {
+        let today = self.since.date().and_time(self.time);
+        if today > self.since {
+            today
+        } else {
+            today + Duration::days(1)
+        }
+    }
+
+    pub fn is_due(&self, now: NaiveDateTime) -> bool {
+        self.enabled && self.next() <= now
+    }
+
+    // rung at now, wait for the next day
+    pub fn ring(&mut self, now: NaiveDateTime) {
+        self.since = now;
+    }
+
+    // alarm turned on doesn't ring for the time passed
+    pub fn toggle(&mut self, now: NaiveDateTime) {
+        self.enabled = !self.enabled;
+        self.since = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_alarm_rings_once_a_day() {
+        let time = NaiveTime::from_hms_opt(7, 30, 0).unwrap();
+        let mut alarm = Alarm::new(time, Wake::Fm, true, at(23, 0));
+        // time of today is passed
+        assert!(!alarm.is_due(at(23, 30)));
+        assert_eq!(alarm.next(), at(7, 30) + Duration::days(1));
+        let morning = at(7, 31) + Duration::days(1);
+        assert!(alarm.is_due(morning));
+        alarm.ring(morning);
+        assert!(!alarm.is_due(morning + Duration::hours(1)));
+        assert!(alarm.is_due(morning + Duration::days(1)));
+        alarm.toggle(morning);
+        assert!(!alarm.is_due(morning + Duration::days(1)));
+    }
+
+    #[test]
+    fn test_parse_alarm() {
+        let entry = |time: &str, playlist, fm| Entry {
+            time: time.to_owned(),
+            playlist,
+            fm,
+            enabled: None,
+        };
+        let alarm = Alarm::parse(entry("07:05", Some(42), None), at(0, 0)).unwrap();
+        assert_eq!(alarm.time, NaiveTime::from_hms_opt(7, 5, 0).unwrap());
+        assert_eq!(alarm.wake, Wake::Playlist(42));
+        assert!(alarm.enabled);
+        let alarm = Alarm::parse(entry("6:00", None, Some(true)), at(0, 0)).unwrap();
+        assert_eq!(alarm.wake, Wake::Fm);
+        assert!(Alarm::parse(entry("25:00", None, Some(true)), at(0, 0)).is_err());
+        assert!(Alarm::parse(entry("07:00", None, None), at(0, 0)).is_err());
+    }
+}
@@ -24,7 +24,7 @@ use super::model::artist::{Artist, TopArtistRes};
 use super::model::dj::{DjProgram, DjRadio, ProgramDetailRes, ProgramsRes, SubDjRadioRes};
 use super::model::lyric::{Lyric, LyricRes};
 use super::model::playlist::{
-    PersonalFmRes, Playlist, PlaylistDetail, PlaylistDetailRes, PlaylistRes, TopPlaylistRes, Track, UidPlaylistRes
+    PersonalFmRes, Playlist, PlaylistDetail, PlaylistDetailRes, PlaylistRes, SimiSongRes, TopPlaylistRes, Track, UidPlaylistRes
 };
 use super::model::search::{
     SearchAlbumResult, SearchAlbums, SearchArtistResult, SearchArtists, SearchDjRadios,
@@ -60,7 +60,20 @@ impl failure::Fail for ApiError {}
 
 impl fmt::Display for ApiError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Netease Cloud Music API reported an error")
+        match self {
+            ApiError::Unauthorized => write!(f, "Netease Cloud Music API unauthorized"),
+            ApiError::RateLimited(Some(seconds)) => write!(
+                f,
+                "Netease Cloud Music API rate limited, retry after {}s",
+                seconds
+            ),
+            ApiError::RateLimited(None) => write!(f, "Netease Cloud Music API rate limited"),
+            ApiError::Other(status) => write!(
+                f,
+                "Netease Cloud Music API reported an error with status {}",
+                status
+            ),
+        }
     }
 }
 
@@ -122,8 +135,7 @@ impl CloudMusic {
         params.insert("csrf_token".to_owned(), csrf_token);
         let params = Encrypt::encrypt_login(params);
         // let param = json!(params);
-        let a = self.internal_call(Method::POST, &url, Some(params));
-        Ok(a.unwrap())
+        self.internal_call(Method::POST, url, Some(params))
     }
 
     fn internal_call(
@@ -185,15 +197,13 @@ impl CloudMusic {
                 builder
             };
 
-            builder.send().unwrap()
+            builder.send()?
         };
         let mut buf = String::new();
 
         self.store_cookies(&response);
 
-        response
-            .read_to_string(&mut buf)
-            .expect("failed to read response");
+        response.read_to_string(&mut buf)?;
         if response.status().is_success() {
             Ok(buf)
         } else if response.status() == 403 {
@@ -302,18 +312,16 @@ impl CloudMusic {
     }
 
     // get song url
-    pub fn get_song_url(&self, song_id: &str) -> Result<Song, failure::Error> {
+    // br is bitrate of the song, 999000 for flac
+    pub fn get_song_url(&self, song_id: &str, br: u32) -> Result<Song, failure::Error> {
         let url = format!("/weapi/song/enhance/player/url");
         let mut params = HashMap::new();
-        let song_id = song_id.to_string().parse::<u32>().unwrap();
+        let song_id = song_id.to_string().parse::<u32>()?;
         params.insert(
             "ids".to_owned(),
             serde_json::to_string(&vec![song_id]).unwrap_or("[]".to_owned()),
         );
-        // set br to 320000
-        // if set br 990000 will get the flac music link
-        // but now I haven't finish the flac decode
-        params.insert("br".to_owned(), 320000.to_string());
+        params.insert("br".to_owned(), br.to_string());
 
         // send request
         let result = self.post(&url, &mut params)?;
@@ -568,6 +576,19 @@ impl CloudMusic {
         Ok(res.data)
     }
 
+    // songs similar to a song
+    pub fn simi_songs(&self, track_id: &str) -> Result<Vec<Track>, failure::Error> {
+        let url = "/weapi/v1/discovery/simiSong";
+        let mut params = HashMap::new();
+        params.insert("songid".to_owned(), track_id.to_string());
+        params.insert("limit".to_owned(), 50.to_string());
+        params.insert("offset".to_owned(), 0.to_string());
+
+        let result = self.post(url, &mut params)?;
+        let res = self.convert_result::<SimiSongRes>(&result)?;
+        Ok(res.songs)
+    }
+
     // top songlist
     pub fn top_playlists(&self, limit: i32, offset: i32) -> Result<Vec<Playlist>, failure::Error> {
         let url = format!("/weapi/playlist/list");
@@ -1,14 +1,24 @@
+use super::alarm::{Alarm, Wake};
 use super::api::CloudMusic;
+use super::download::{DownloadEvent, Downloader};
 use super::handlers::TrackState;
 use super::model::album::Album;
 use super::model::artist::Artist;
 use super::model::dj::{DjProgram, DjRadio};
 use super::model::lyric::Lyric;
 use super::model::playlist::{Playlist, Track};
-use super::player::Nplayer;
+use super::model::song::Song;
+use super::player::{devices, Detail, Media, Nplayer, PlayerEvent};
+use super::queue::{Queue, Source};
+use super::resolve::{Request, Resolved, Resolver, UrlFor};
+use super::settings::{Quality, Settings};
+use super::sleep::{Sleep, SleepTimer};
 use super::ui::circle::{Circle, CIRCLE, CIRCLE_TICK};
 
-use rand::Rng;
+use chrono::Local;
+use failure::err_msg;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tui::layout::Rect;
 use tui::style::Color;
 
@@ -18,13 +28,17 @@ const DEFAULT_ROUTE: Route = Route {
     hovered_block: ActiveBlock::Recommend,
 };
 
-pub const RECOMMEND_OPTIONS: [&str; 6] = [
+pub const RECOMMEND_OPTIONS: [&str; 10] = [
     "My Playlist",
     "Discover",
     "Personal FM",
     "Hot Albums",
     "Hot Artists",
     "Subed DjRadios",
+    "Downloads",
+    "Devices",
+    "Alarms",
+    "Play Queue",
 ];
 
 #[derive(Clone, PartialEq, Debug)]
@@ -56,6 +70,10 @@ pub enum RouteId {
     Playing,
     DjRadio,
     DjProgram,
+    Downloads,
+    Devices,
+    Alarms,
+    Queue,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -80,6 +98,10 @@ pub enum ActiveBlock {
     Msg,
     DjRadio,
     DjProgram,
+    Downloads,
+    Devices,
+    Alarms,
+    Queue,
 }
 
 #[derive(Clone)]
@@ -141,6 +163,20 @@ pub struct ProgramTable {
     pub name: String,
 }
 
+// output devices of sound card
+#[derive(Clone, Debug, Default)]
+pub struct DeviceTable {
+    pub devices: Vec<String>,
+    pub selected_index: usize,
+}
+
+// alarms of config
+#[derive(Clone, Debug, Default)]
+pub struct AlarmTable {
+    pub alarms: Vec<Alarm>,
+    pub selected_index: usize,
+}
+
 #[derive(Clone)]
 pub struct Recommend {
     pub selected_index: usize,
@@ -192,6 +228,15 @@ impl TabsState {
 pub struct App {
     navigation_stack: Vec<Route>,
     pub player: Nplayer,
+    pub downloader: Downloader,
+    // api requests which would hold the ui
+    resolver: Resolver,
+    // id of track to load when its url comes
+    resolving: Option<i64>,
+    // song whose similar songs are asked, and if the first one plays when they come
+    similar: Option<(i64, bool)>,
+    pub device_table: DeviceTable,
+    pub alarm_table: AlarmTable,
     pub size: Rect,
     pub input: Vec<char>,
     pub input_idx: usize,
@@ -204,8 +249,22 @@ pub struct App {
     pub cloud_music: Option<CloudMusic>,
     pub recommend: Recommend,
     pub duration_ms: Option<u64>,
-    pub my_playlist: TrackTable,
+    pub queue: Queue,
+    // next track (index in queue) preloaded by player
+    pub prefetch: Option<(usize, Track)>,
     pub repeat_state: RepeatState,
+    pub quality: Quality,
+    pub sleep: Option<SleepTimer>,
+    // volume fade out before sleep
+    sleep_fade: Duration,
+    // volume ramping up after alarm starts
+    alarm_ramp: Duration,
+    // when the last alarm started playing, none after ramping up
+    ramp_start: Option<Instant>,
+    // similar songs are played after the end of queue with repeat off
+    pub play_similar: bool,
+    // url info of songs asked in this session, shows bitrate and format
+    pub song_info: HashMap<i64, Song>,
     pub fm_state: bool,
     pub search_results: SearchResult,
     pub tabs: TabsState,
@@ -228,10 +287,19 @@ pub struct App {
 }
 
 impl App {
-    pub fn new() -> App {
+    pub fn new(settings: Settings) -> App {
         App {
             navigation_stack: vec![DEFAULT_ROUTE],
-            player: Nplayer::new(),
+            player: Nplayer::new(&settings),
+            downloader: Downloader::new(&settings),
+            resolver: Resolver::new(),
+            resolving: None,
+            similar: None,
+            device_table: Default::default(),
+            alarm_table: AlarmTable {
+                alarms: settings.alarms.to_owned(),
+                selected_index: 0,
+            },
             size: Rect::default(),
             input: vec![],
             input_idx: 0,
@@ -244,8 +312,16 @@ impl App {
             track_table: Default::default(),
             cloud_music: Some(CloudMusic::default()),
             recommend: Recommend { selected_index: 0 },
-            my_playlist: Default::default(),
+            queue: Default::default(),
+            prefetch: None,
             repeat_state: RepeatState::All,
+            quality: settings.quality,
+            sleep: None,
+            sleep_fade: Duration::from_secs(settings.sleep_fade),
+            alarm_ramp: Duration::from_secs(settings.alarm_ramp),
+            ramp_start: None,
+            play_similar: settings.play_similar,
+            song_info: HashMap::new(),
             fm_state: false,
             search_results: SearchResult {
                 tracks: None,
@@ -292,6 +368,11 @@ impl App {
 
     // update app every tick
     pub fn update_on_tick(&mut self) {
+        self.handle_player_events();
+        self.check_sleep();
+        self.check_alarm();
+        self.handle_download_events();
+        self.handle_resolved_events();
         let current_route = self.get_current_route();
         if current_route.active_block == ActiveBlock::Msg {
             if self.msg_control > 2 {
@@ -314,6 +395,30 @@ impl App {
                             Some(ActiveBlock::DjProgram),
                         );
                     }
+                    RouteId::Downloads => {
+                        self.set_current_route_state(
+                            Some(ActiveBlock::Downloads),
+                            Some(ActiveBlock::Downloads),
+                        );
+                    }
+                    RouteId::Devices => {
+                        self.set_current_route_state(
+                            Some(ActiveBlock::Devices),
+                            Some(ActiveBlock::Devices),
+                        );
+                    }
+                    RouteId::Alarms => {
+                        self.set_current_route_state(
+                            Some(ActiveBlock::Alarms),
+                            Some(ActiveBlock::Alarms),
+                        );
+                    }
+                    RouteId::Queue => {
+                        self.set_current_route_state(
+                            Some(ActiveBlock::Queue),
+                            Some(ActiveBlock::Queue),
+                        );
+                    }
                     _ => {
                         self.set_current_route_state(
                             Some(ActiveBlock::TrackTable),
@@ -326,12 +431,17 @@ impl App {
                 self.msg_control += 1;
             }
         }
-        if self.player.is_playing() {
-            // get positon
+        if self.current_playing.is_some() {
+            // get positon, it also moves when seeking in pause
             self.song_progress_ms = match self.player.get_position() {
                 Some(ms) => ms,
                 None => 0 as u64,
             };
+            if let Some(lyrics) = &self.lyric {
+                self.lyric_index = App::lyric_index(lyrics, self.song_progress_ms);
+            }
+        }
+        if self.player.is_playing() {
             let current_route = self.get_current_route();
             if current_route.active_block == ActiveBlock::Playing {
                 if self.circle_flag {
@@ -347,38 +457,62 @@ impl App {
                 }
                 self.circle_flag = !self.circle_flag;
             }
-            match &self.lyric {
-                Some(lyrics) => {
-                    let next_lyric = lyrics.get(self.lyric_index + 1);
-                    // check current ms and lyric timeline
-                    match next_lyric {
-                        Some(next_lyric) => {
-                            if self.song_progress_ms as u128 >= next_lyric.timeline.as_millis() {
-                                self.lyric_index += 1;
-                            }
-                        }
-                        None => {}
+
+        }
+    }
+
+    // handle state events from player thread
+    fn handle_player_events(&mut self) {
+        for event in self.player.poll_events() {
+            match event {
+                PlayerEvent::Loaded(..) => {
+                    self.duration_ms = self.player.get_duration();
+                    self.prefetch_next();
+                }
+                PlayerEvent::TrackChanged(..) => {
+                    self.log_track();
+                    if let Some((index, track)) = self.prefetch.take() {
+                        self.queue.set_current(index);
+                        self.init_playing(track);
+                    }
+                    self.duration_ms = self.player.get_duration();
+                    if self.sleep_track_finished() {
+                        // next track was queued before the timer was set
+                        self.sleep_now();
+                        self.player.position(0);
                     }
+                    self.prefetch_next();
                 }
-                None => {}
-            }
-
-            // check progress duration
-            match self.duration_ms {
-                Some(duration_ms) => {
-                    // caculate song duration and current progress
-                    // if difference less than 1000 means it less than 1s. skip it
-                    if (duration_ms - self.song_progress_ms) < 1000 {
-                        // log track
-                        self.log_track();
-                        self.skip_track(TrackState::Forword)
+                PlayerEvent::EndOfTrack => {
+                    // log track
+                    self.log_track();
+                    if self.sleep_track_finished() {
+                        // nothing is loaded, the queue goes on when playback resumes
+                        self.sleep_now();
+                    } else {
+                        self.skip_track(TrackState::Forword);
                     }
                 }
-                None => {}
+                PlayerEvent::Error(e) => {
+                    error!("player error {}", e);
+                    self.msg = format!("play track failed: {}", e);
+                    self.set_current_route_state(Some(ActiveBlock::Msg), None);
+                }
+                _ => {}
             }
         }
     }
 
+    // find the lyric line of current progress
+    // check current ms and lyric timeline, works for seek backwards too
+    pub fn lyric_index(lyrics: &[Lyric], progress_ms: u64) -> usize {
+        lyrics
+            .iter()
+            .take_while(|lyric| progress_ms as u128 >= lyric.timeline.as_millis())
+            .count()
+            .saturating_sub(1)
+    }
+
     pub fn next_index<T>(
         selection_data: &[T],
         selection_index: Option<usize>,
@@ -411,60 +545,457 @@ impl App {
         }
     }
 
-    pub fn skip_track(&mut self, state: TrackState) {
-        match self.fm_state {
-            false => {
-                match self.repeat_state {
-                    RepeatState::Track => {
-                        // loop current song
-                        match self.current_playing.to_owned() {
-                            Some(track) => {
-                                self.start_playback(track.to_owned());
-                            }
-                            None => {
-                                panic!("error");
+    // the next track of queue follow repeat state
+    fn next_track(&mut self) -> Option<(usize, Track)> {
+        let list = &mut self.queue;
+        let next_index = match self.fm_state {
+            false => match self.repeat_state {
+                RepeatState::Track => {
+                    return self
+                        .current_playing
+                        .to_owned()
+                        .map(|track| (list.current.unwrap_or(0), track));
+                }
+                RepeatState::All => App::next_index(&list.items, list.current, TrackState::Forword),
+                RepeatState::Shuffle => list.next_shuffled()?,
+                // stop after the last track
+                RepeatState::Off => {
+                    let next_index = list.current.map_or(0, |current| current + 1);
+                    if next_index >= list.items.len() {
+                        return None;
+                    }
+                    next_index
+                }
+            },
+            true => {
+                let next_index = App::next_index(&list.items, list.current, TrackState::Forword);
+                // personal fm need refill at the end
+                if next_index == 0 {
+                    return None;
+                }
+                next_index
+            }
+        };
+        list.track(next_index)
+            .map(|track| (next_index, track.to_owned()))
+    }
+
+    // resolve next track and let player download it while current track playing
+    pub fn prefetch_next(&mut self) {
+        // sleep timer pauses after current track, so it ends without a next one
+        if self.sleep.as_ref().and_then(|timer| timer.tracks()) == Some(1) {
+            self.prefetch = None;
+            self.player.clear_preload();
+            return;
+        }
+        if self.current_playing.is_none() {
+            self.prefetch = None;
+            return;
+        }
+        // similar songs are added before the end for gapless playback
+        if self.is_end_of_queue() {
+            self.request_similar(false);
+        }
+        let prefetched = self.prefetch.take();
+        if let Some((index, track)) = self.next_track() {
+            // next track is the same after editing the queue, it's preloaded already
+            if prefetched.is_some_and(|(_, prefetched)| prefetched.id == track.id) {
+                self.prefetch = Some((index, track));
+                return;
+            }
+            match self.track_media(&track) {
+                Ok(Some(media)) => self.preload(media, &track),
+                // preloaded when its url comes
+                Ok(None) => self.request_url(&track, UrlFor::Preload),
+                Err(_) => return,
+            }
+            self.prefetch = Some((index, track));
+        } else {
+            // queue changed, drop the track preloaded before
+            self.player.clear_preload();
+        }
+    }
+
+    fn preload(&mut self, media: Media, track: &Track) {
+        // keep gapless for tracks of the same album
+        let same_album = match (&self.current_playing, &track.album) {
+            (Some(current), Some(album)) => match &current.album {
+                Some(current_album) => current_album.id.is_some() && current_album.id == album.id,
+                None => false,
+            },
+            _ => false,
+        };
+        self.player.preload(media, !same_album, App::track_detail(track));
+    }
+
+    // downloaded or cached track is played without asking its url
+    // none if its url is needed
+    fn track_media(&self, track: &Track) -> Result<Option<Media>, failure::Error> {
+        let id = track.id.ok_or_else(|| err_msg("track has no id"))?;
+        if let Some(file) = self.downloader.file(id) {
+            return Ok(Some(Media::File(file)));
+        }
+        let br = self.quality.bitrate();
+        if self.player.is_cached(id, br) {
+            return Ok(Some(Media::Song { id, br, url: None }));
+        }
+        match &self.cloud_music {
+            Some(_) => Ok(None),
+            None => Err(err_msg("not login")),
+        }
+    }
+
+    // ask url of track in resolver thread
+    fn request_url(&self, track: &Track, url_for: UrlFor) {
+        if let Some(id) = track.id {
+            let br = self.quality.bitrate();
+            self.resolver.request(Request::SongUrl(id, br, url_for));
+        }
+    }
+
+    // media of song from its url info
+    fn song_media(&mut self, id: i64, br: u32, song: Song) -> Result<Media, failure::Error> {
+        let url = song.url.to_owned();
+        self.song_info.insert(id, song);
+        match url {
+            Some(url) => Ok(Media::Song {
+                id,
+                br,
+                url: Some(url),
+            }),
+            None => Err(err_msg("get track url failed")),
+        }
+    }
+
+    // handle results of api requests from resolver thread
+    fn handle_resolved_events(&mut self) {
+        for event in self.resolver.poll_events() {
+            self.handle_resolved(event);
+        }
+    }
+
+    fn handle_resolved(&mut self, event: Resolved) {
+        match event {
+            Resolved::SongUrl(id, br, url_for, song) => {
+                let media = song.and_then(|song| self.song_media(id, br, song));
+                match url_for {
+                    UrlFor::Play => {
+                        // another track is played since it was asked
+                        if self.resolving != Some(id) {
+                            return;
+                        }
+                        self.resolving = None;
+                        let detail = self.current_playing.as_ref().map(App::track_detail);
+                        match (media, detail) {
+                            (Ok(media), Some(detail)) => self.player.load(media, detail),
+                            (Ok(_), None) => {}
+                            (Err(e), _) => {
+                                self.msg = e.to_string();
+                                self.set_current_route_state(Some(ActiveBlock::Msg), None);
                             }
-                        };
+                        }
                     }
-                    RepeatState::All => {
-                        // loop current my playlist
-                        let list = &mut self.my_playlist;
-                        let next_index =
-                            App::next_index(&list.tracks, Some(list.selected_index), state);
-                        list.selected_index = next_index;
-
-                        let track_playing =
-                            list.tracks.get(next_index.to_owned()).unwrap().to_owned();
-                        self.start_playback(track_playing);
+                    UrlFor::Preload => {
+                        // next track may be changed since it was asked
+                        let next = match &self.prefetch {
+                            Some((_, next)) if next.id == Some(id) => next.to_owned(),
+                            _ => return,
+                        };
+                        match media {
+                            Ok(media) => self.preload(media, &next),
+                            Err(e) => error!("get url of next track {} failed {}", id, e),
+                        }
                     }
-                    RepeatState::Shuffle => {
-                        let list = &mut self.my_playlist;
-                        let mut rng = rand::thread_rng();
-                        let next_index = rng.gen_range(0, list.tracks.len());
-                        list.selected_index = next_index;
-
-                        let track_playing =
-                            list.tracks.get(next_index.to_owned()).unwrap().to_owned();
-                        self.start_playback(track_playing);
+                }
+            }
+            Resolved::Lyric(id, lyric) => {
+                if self.current_playing.as_ref().and_then(|track| track.id) == Some(id) {
+                    // downloaded tracks play offline, the lyric is just missing then
+                    self.lyric = Some(lyric.unwrap_or_else(|_| App::no_lyric()));
+                }
+            }
+            Resolved::Similar(id, similar) => {
+                let play = match self.similar.take() {
+                    Some((asked, play)) if asked == id => play,
+                    other => {
+                        self.similar = other;
+                        return;
                     }
-                    _ => {}
+                };
+                let similar = similar.unwrap_or_else(|e| {
+                    error!("get similar songs failed {}", e);
+                    vec![]
+                });
+                self.append_similar(id, similar, play);
+            }
+        }
+    }
+
+    // song info player can't get from the file
+    fn track_detail(track: &Track) -> Detail {
+        Detail {
+            id: track.id,
+            album: track
+                .album
+                .as_ref()
+                .and_then(|album| album.id)
+                .map(i64::from),
+            radio: track.radio,
+            duration: track.duration.map(Duration::from_millis),
+        }
+    }
+
+    // output devices of sound card, selected one is the playing device
+    pub fn list_devices(&mut self) {
+        let devices = devices();
+        let selected_index = match self.player.device() {
+            Some(device) => devices
+                .iter()
+                .position(|name| name == device)
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.device_table = DeviceTable {
+            devices,
+            selected_index,
+        };
+    }
+
+    // play to selected device
+    pub fn select_device(&mut self) {
+        let index = self.device_table.selected_index;
+        if let Some(device) = self.device_table.devices.get(index) {
+            self.player.set_device(device.to_owned());
+        }
+    }
+
+    // play a list from index, the list becomes the queue
+    pub fn play_list(&mut self, tracks: Vec<Track>, index: usize, source: Source) {
+        if let Some(track) = tracks.get(index).cloned() {
+            self.queue.replace(tracks, index, source);
+            self.fm_state = false;
+            self.start_playback(track);
+        }
+    }
+
+    // play track after the playing one and keep the rest of queue
+    pub fn play_now(&mut self, track: Track, source: Source) {
+        self.queue.play_now(track.to_owned(), source);
+        self.fm_state = false;
+        self.start_playback(track);
+    }
+
+    pub fn play_next(&mut self, track: Track, source: Source) {
+        self.msg = format!("Play next: {}", track.name.to_owned().unwrap_or_default());
+        self.queue.play_next(track, source);
+        self.set_current_route_state(Some(ActiveBlock::Msg), None);
+        self.prefetch_next();
+    }
+
+    pub fn append_tracks(&mut self, tracks: Vec<Track>, source: Source) {
+        self.msg = format!("{} tracks added to queue", tracks.len());
+        self.queue.append(tracks, source);
+        self.set_current_route_state(Some(ActiveBlock::Msg), None);
+        self.prefetch_next();
+    }
+
+    // play selected track of queue block
+    pub fn play_queue_selected(&mut self) {
+        let index = self.queue.selected_index;
+        if let Some(track) = self.queue.track(index).cloned() {
+            self.queue.set_current(index);
+            self.start_playback(track);
+        }
+    }
+
+    pub fn remove_from_queue(&mut self) {
+        self.queue.remove(self.queue.selected_index);
+        self.prefetch_next();
+    }
+
+    // move selected track of queue up or down
+    pub fn move_in_queue(&mut self, up: bool) {
+        let index = self.queue.selected_index;
+        if up {
+            self.queue.move_up(index);
+        } else {
+            self.queue.move_down(index);
+        }
+        self.prefetch_next();
+    }
+
+    // remove all tracks but the playing one
+    pub fn clear_queue(&mut self) {
+        self.queue.clear_rest();
+        self.prefetch_next();
+    }
+
+    // switch quality of next tracks
+    pub fn toggle_quality(&mut self) {
+        self.quality = self.quality.next();
+        self.downloader.quality = self.quality;
+        self.msg = format!("Quality: {}", self.quality);
+        self.set_current_route_state(Some(ActiveBlock::Msg), None);
+        // next track is preloaded again in new quality
+        self.prefetch = None;
+        self.prefetch_next();
+    }
+
+    pub fn skip_track(&mut self, state: TrackState) {
+        // play the preloaded track
+        if state == TrackState::Forword {
+            if let Some((index, track)) = self.prefetch.take() {
+                self.queue.set_current(index);
+                self.start_playback(track);
+                return;
+            }
+        }
+        if self.fm_state {
+            // use queue for play personal fm
+            let list = &mut self.queue;
+            let next_index = App::next_index(&list.items, list.current, state);
+            if next_index == 0 {
+                if let Ok(tracks) = self.cloud_music.as_ref().unwrap().personal_fm() {
+                    list.replace(tracks, 0, Source::Fm);
                 }
             }
-            true => {
-                // use my playlist for play personal fm
-                let list = &mut self.my_playlist;
-                let next_index = App::next_index(&list.tracks, Some(list.selected_index), state);
-                if next_index == 0 {
-                    if let Ok(tracks) = self.cloud_music.as_ref().unwrap().personal_fm() {
-                        list.tracks = tracks.to_owned();
+            self.play_queue_index(Some(next_index));
+            return;
+        }
+        // nothing to skip to
+        if self.queue.items.is_empty() {
+            return;
+        }
+        let list = &mut self.queue;
+        let next_index = match self.repeat_state {
+            // loop current song, or the current one of queue if nothing is playing
+            RepeatState::Track => match self.current_playing.to_owned() {
+                Some(track) => {
+                    self.start_playback(track);
+                    return;
+                }
+                None => list.current,
+            },
+            // loop queue
+            RepeatState::All => Some(App::next_index(&list.items, list.current, state)),
+            // walk the shuffled order, replay current one at its start
+            RepeatState::Shuffle => match state {
+                TrackState::Forword => list.next_shuffled(),
+                TrackState::Backword => list.previous_shuffled().or(list.current),
+            },
+            // stop at the end, replay the first track at the start
+            RepeatState::Off => match state {
+                TrackState::Forword => {
+                    let next_index = list.current.map_or(0, |current| current + 1);
+                    if next_index >= list.items.len() {
+                        self.end_of_queue();
+                        return;
                     }
+                    Some(next_index)
                 }
-                list.selected_index = next_index;
+                TrackState::Backword => list
+                    .current
+                    .map(|current| current.saturating_sub(1))
+                    .or(Some(0)),
+            },
+        };
+        self.play_queue_index(next_index);
+    }
 
-                let track_playing = list.tracks.get(next_index.to_owned()).unwrap().to_owned();
-                self.start_playback(track_playing);
+    // play track of queue at index, nothing for an empty queue
+    fn play_queue_index(&mut self, index: Option<usize>) {
+        let index = match index {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(track) = self.queue.track(index).cloned() {
+            self.queue.set_current(index);
+            self.start_playback(track);
+        }
+    }
+
+    // last track of queue is played with repeat off
+    // go on with similar songs if it's set, or stop
+    fn end_of_queue(&mut self) {
+        if !self.request_similar(true) {
+            self.stop_queue();
+        }
+    }
+
+    fn stop_queue(&mut self) {
+        self.player.stop();
+        self.msg = "End of queue".to_owned();
+        self.set_current_route_state(Some(ActiveBlock::Msg), None);
+    }
+
+    // playing track is the last one of queue and nothing is played after it
+    fn is_end_of_queue(&self) -> bool {
+        !self.fm_state
+            && self.repeat_state == RepeatState::Off
+            && self
+                .queue
+                .current
+                .is_some_and(|current| current + 1 >= self.queue.items.len())
+    }
+
+    // ask songs similar to the last track of queue like personal fm refills
+    // play is for the end of queue, the first one plays when they come
+    // true if they are asked
+    fn request_similar(&mut self, play: bool) -> bool {
+        if !self.play_similar || self.cloud_music.is_none() {
+            return false;
+        }
+        let id = match self.queue.items.last().and_then(|item| item.track.id) {
+            Some(id) => id,
+            None => return false,
+        };
+        match &mut self.similar {
+            Some((asked, asked_play)) if *asked == id => *asked_play |= play,
+            _ => {
+                self.similar = Some((id, play));
+                self.resolver.request(Request::Similar(id));
             }
         }
+        true
+    }
+
+    // append similar songs of the last track, the queue may be changed since they were asked
+    fn append_similar(&mut self, id: i64, similar: Vec<Track>, play: bool) {
+        let last = match self.queue.items.last() {
+            Some(item) if item.track.id == Some(id) => item.track.to_owned(),
+            _ => return,
+        };
+        // skip songs already in queue
+        let similar = similar
+            .into_iter()
+            .filter(|song| !self.queue.items.iter().any(|item| item.track == *song))
+            .collect::<Vec<Track>>();
+        if similar.is_empty() {
+            if play {
+                self.stop_queue();
+            }
+            return;
+        }
+        let index = self.queue.items.len();
+        let name = last.name.unwrap_or_default();
+        self.queue.append(similar, Source::Similar(name));
+        if play {
+            self.play_queue_index(Some(index));
+        } else {
+            self.prefetch_next();
+        }
+    }
+
+    // keep playing similar songs after the end of queue with repeat off
+    pub fn toggle_play_similar(&mut self) {
+        self.play_similar = !self.play_similar;
+        self.msg = if self.play_similar {
+            "Play similar songs after the end of queue".to_owned()
+        } else {
+            "Stop at the end of queue".to_owned()
+        };
+        self.set_current_route_state(Some(ActiveBlock::Msg), None);
+        self.prefetch_next();
     }
 
     pub fn get_current_route(&self) -> &Route {
@@ -522,6 +1053,150 @@ impl App {
             RepeatState::Shuffle => RepeatState::All,
         };
         self.repeat_state = next_repeat_state;
+        self.queue.set_shuffle(self.repeat_state == RepeatState::Shuffle);
+        // next track changes with repeat state
+        self.prefetch_next();
+    }
+
+    // speed up or slow down playing dj program
+    pub fn change_speed(&mut self, faster: bool) {
+        match &self.current_playing {
+            Some(track) if track.radio.is_some() => {
+                if faster {
+                    self.player.increase_speed();
+                } else {
+                    self.player.decrease_speed();
+                }
+            }
+            _ => {
+                self.msg = "speed is only for dj programs".to_owned();
+                self.set_current_route_state(Some(ActiveBlock::Msg), None);
+            }
+        }
+    }
+
+    // set or cancel sleep timer
+    pub fn set_sleep(&mut self, sleep: Option<Sleep>) {
+        self.sleep = sleep.map(|sleep| SleepTimer::new(sleep, self.sleep_fade));
+        self.ramp_start = None;
+        self.player.fade(1.0);
+        // preloaded track is dropped for the last track of timer
+        self.prefetch_next();
+    }
+
+    // next preset of sleep timer by minutes or tracks, off after the last one
+    pub fn next_sleep(&mut self, tracks: bool) {
+        let current = self.sleep.as_ref().map(|timer| timer.sleep());
+        self.set_sleep(Sleep::next(current, tracks));
+    }
+
+    // fade out volume before sleep, pause when the time is up
+    fn check_sleep(&mut self) {
+        let timer = match &self.sleep {
+            Some(timer) => timer,
+            None => return,
+        };
+        if timer.is_due() {
+            self.sleep_now();
+            return;
+        }
+        let volume = timer.volume(timer.remaining(self.track_left()));
+        self.player.fade(volume);
+    }
+
+    // real time left of playing track
+    pub fn track_left(&self) -> Option<Duration> {
+        let left = self
+            .player
+            .get_duration()?
+            .saturating_sub(self.player.get_position()?);
+        Some(Duration::from_millis(left).div_f32(self.player.get_speed()))
+    }
+
+    fn sleep_track_finished(&mut self) -> bool {
+        self.sleep
+            .as_mut()
+            .is_some_and(|timer| timer.track_finished())
+    }
+
+    fn sleep_now(&mut self) {
+        self.sleep = None;
+        self.player.pause();
+        self.player.fade(1.0);
+    }
+
+    // start the alarm which is due, then ramp up its volume from 0
+    fn check_alarm(&mut self) {
+        let now = Local::now().naive_local();
+        let due = self
+            .alarm_table
+            .alarms
+            .iter_mut()
+            .find(|alarm| alarm.is_due(now))
+            .map(|alarm| {
+                alarm.ring(now);
+                alarm.wake
+            });
+        if let Some(wake) = due {
+            self.wake_up(wake);
+        }
+        if let Some(start) = self.ramp_start {
+            // no ramp means full volume at once
+            let volume = if self.alarm_ramp.as_millis() == 0 {
+                1.0
+            } else {
+                start.elapsed().as_secs_f32() / self.alarm_ramp.as_secs_f32()
+            };
+            if volume >= 1.0 {
+                self.ramp_start = None;
+                self.player.fade(1.0);
+            } else {
+                self.player.fade(volume);
+            }
+        }
+    }
+
+    fn wake_up(&mut self, wake: Wake) {
+        self.sleep = None;
+        self.player.fade(0.0);
+        self.ramp_start = Some(Instant::now());
+        match wake {
+            Wake::Playlist(id) => match self.playlist_tracks(&id.to_string()) {
+                Ok((name, tracks)) if !tracks.is_empty() => {
+                    let track = tracks[0].to_owned();
+                    self.queue.replace(tracks, 0, Source::Playlist(name));
+                    self.fm_state = false;
+                    self.start_playback(track);
+                }
+                Ok(_) => {
+                    self.msg = format!("Alarm playlist {} is empty", id);
+                    self.set_current_route_state(Some(ActiveBlock::Msg), None);
+                }
+                Err(e) => {
+                    self.msg = format!("Alarm playlist {} failed: {}", id, e);
+                    self.set_current_route_state(Some(ActiveBlock::Msg), None);
+                }
+            },
+            Wake::Fm => {
+                // wake up with the current track when fm has nothing
+                if !self.set_fm_mode() {
+                    if self.current_playing.is_some() {
+                        self.player.play();
+                    } else {
+                        self.ramp_start = None;
+                        self.player.fade(1.0);
+                    }
+                }
+            }
+        }
+    }
+
+    // turn selected alarm on or off for this session
+    pub fn toggle_alarm(&mut self) {
+        let index = self.alarm_table.selected_index;
+        if let Some(alarm) = self.alarm_table.alarms.get_mut(index) {
+            alarm.toggle(Local::now().naive_local());
+        }
     }
 
     // handle error
@@ -531,29 +1206,68 @@ impl App {
     }
 
     pub fn get_playlist_tracks(&mut self, playlist_id: String) {
+        if let Ok((name, tracks)) = self.playlist_tracks(&playlist_id) {
+            self.track_table = TrackTable {
+                tracks,
+                name,
+                selected_index: 0,
+            }
+        }
+        self.push_navigation_stack(RouteId::TrackTable, ActiveBlock::TrackTable)
+    }
+
+    // name and tracks of playlist
+    fn playlist_tracks(&self, playlist_id: &str) -> Result<(String, Vec<Track>), failure::Error> {
         match &self.cloud_music {
             Some(api) => {
-                if let Ok(playlist_tracks) = api.playlist_detail(&playlist_id) {
-                    let tracks = playlist_tracks
-                        .tracks
-                        .into_iter()
-                        .map(|t| Track {
-                            name: t.name,
-                            id: t.id,
-                            artists: t.ar,
-                            album: t.al,
-                        })
-                        .collect();
-                    self.track_table = TrackTable {
-                        tracks: tracks,
-                        name: playlist_tracks.name.unwrap(),
-                        selected_index: 0,
-                    }
+                let playlist_tracks = api.playlist_detail(playlist_id)?;
+                let tracks = playlist_tracks
+                    .tracks
+                    .into_iter()
+                    .map(|t| Track {
+                        name: t.name,
+                        id: t.id,
+                        artists: t.ar,
+                        album: t.al,
+                        no: t.no,
+                        duration: t.dt,
+                        radio: None,
+                    })
+                    .collect();
+                Ok((playlist_tracks.name.unwrap_or_default(), tracks))
+            }
+            None => Err(err_msg("not login")),
+        }
+    }
+
+    // save tracks to music dir for offline playing
+    pub fn download_tracks(&mut self, tracks: &[Track]) {
+        let count = self.downloader.download(tracks);
+        self.msg = format!("{} tracks added to download", count);
+        self.set_current_route_state(Some(ActiveBlock::Msg), None);
+    }
+
+    pub fn download_playlist(&mut self, playlist_id: String) {
+        match self.playlist_tracks(&playlist_id) {
+            Ok((_, tracks)) => self.download_tracks(&tracks),
+            Err(e) => self.handle_error(e),
+        }
+    }
+
+    fn handle_download_events(&mut self) {
+        for event in self.downloader.poll_events() {
+            if let DownloadEvent::Failed(_, e) = event {
+                // failed item shows the error in download list
+                // and don't break the search input
+                let current_route = self.get_current_route();
+                if current_route.id != RouteId::Downloads
+                    && current_route.active_block != ActiveBlock::Search
+                {
+                    self.msg = format!("download failed: {}", e);
+                    self.set_current_route_state(Some(ActiveBlock::Msg), None);
                 }
             }
-            None => {}
         }
-        self.push_navigation_stack(RouteId::TrackTable, ActiveBlock::TrackTable)
     }
 
     pub fn get_artist_albums(&mut self, artist_id: String) {
@@ -760,39 +1474,52 @@ impl App {
     }
 
     pub fn start_playback(&mut self, track: Track) {
-        match &self.cloud_music {
-            Some(api) => {
-                let id = track.id.unwrap().to_string();
-                let song = api.get_song_url(&id).unwrap();
-                match song.url {
-                    Some(url) => {
-                        let url = url.to_string();
-                        // init play state
-                        self.duration_ms = None;
-                        self.song_progress_ms = 0;
-                        self.lyric_index = 0;
-                        self.player.play_url(&url);
-                        self.lyric = Some(api.lyric(&id).unwrap());
-                        self.current_playing = Some(track);
-
-                        let mut flag = false;
-                        while !flag {
-                            if self.player.is_playing() {
-                                self.duration_ms = self.player.get_duration();
-                                flag = true;
-                            }
-                        }
-                    }
-                    None => {
-                        self.msg = "get track url failed".to_string();
-                        self.set_current_route_state(Some(ActiveBlock::Msg), None);
-                    }
-                };
+        match self.track_media(&track) {
+            Ok(Some(media)) => {
+                self.prefetch = None;
+                self.resolving = None;
+                self.player.load(media, App::track_detail(&track));
+                self.init_playing(track);
+            }
+            // track is loaded when its url comes, the playing one stops now
+            Ok(None) => {
+                self.prefetch = None;
+                self.resolving = track.id;
+                self.player.stop();
+                self.request_url(&track, UrlFor::Play);
+                self.init_playing(track);
+            }
+            Err(e) => {
+                self.msg = e.to_string();
+                self.set_current_route_state(Some(ActiveBlock::Msg), None);
             }
-            None => {}
         }
     }
 
+    // init play state of new playing track
+    // duration is set when player thread loaded the track
+    fn init_playing(&mut self, track: Track) {
+        self.duration_ms = None;
+        self.song_progress_ms = 0;
+        self.lyric_index = 0;
+        // lyric is set when it comes
+        self.lyric = match (&self.cloud_music, track.id) {
+            (Some(_), Some(id)) => {
+                self.resolver.request(Request::Lyric(id));
+                None
+            }
+            _ => Some(App::no_lyric()),
+        };
+        self.current_playing = Some(track);
+    }
+
+    fn no_lyric() -> Vec<Lyric> {
+        vec![Lyric {
+            value: "no lyric".to_string(),
+            timeline: Duration::new(0, 0),
+        }]
+    }
+
     pub fn log_track(&mut self) {
         match &self.cloud_music {
             Some(api) => match &self.current_playing {
@@ -805,24 +1532,32 @@ impl App {
         }
     }
 
-    pub fn set_fm_mode(&mut self) {
-        match &self.cloud_music {
-            Some(api) => {
-                if let Ok(tracks) = api.personal_fm() {
-                    self.my_playlist = TrackTable {
-                        tracks: tracks,
-                        selected_index: 0,
-                        name: "personal fm".to_string(),
-                    }
-                }
-
-                let track_playing = self.my_playlist.tracks.get(0).unwrap().to_owned();
+    // returns if fm is playing, queue is kept when fm has no tracks
+    pub fn set_fm_mode(&mut self) -> bool {
+        let tracks = match &self.cloud_music {
+            Some(api) => api.personal_fm(),
+            None => return false,
+        };
+        match tracks {
+            Ok(tracks) if !tracks.is_empty() => {
+                let track_playing = tracks[0].to_owned();
+                self.queue.replace(tracks, 0, Source::Fm);
                 self.start_playback(track_playing);
 
                 self.push_navigation_stack(RouteId::PersonalFm, ActiveBlock::PersonalFm);
                 self.fm_state = true;
+                true
+            }
+            Ok(_) => {
+                self.msg = "Personal fm is empty".to_string();
+                self.set_current_route_state(Some(ActiveBlock::Msg), None);
+                false
+            }
+            Err(e) => {
+                self.msg = format!("Personal fm failed: {}", e);
+                self.set_current_route_state(Some(ActiveBlock::Msg), None);
+                false
             }
-            None => {}
         }
     }
 
@@ -832,3 +1567,125 @@ impl App {
         self.set_current_route_state(Some(ActiveBlock::Empty), Some(current_route.hovered_block));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::mpsc::Receiver;
+
+    fn track(id: i64) -> Track {
+        Track {
+            name: Some(format!("song {}", id)),
+            id: Some(id),
+            artists: None,
+            album: None,
+            no: None,
+            duration: None,
+            radio: None,
+        }
+    }
+
+    // app without audio output, api requests are kept for checking
+    fn app(dir: &Path, play_similar: bool) -> (App, Receiver<Request>) {
+        let mut app = App::new(Settings {
+            backend: "null".into(),
+            cache_dir: dir.join("cache"),
+            music_dir: dir.join("music"),
+            play_similar,
+            ..Settings::default()
+        });
+        let (resolver, requests) = Resolver::offline();
+        app.resolver = resolver;
+        (app, requests)
+    }
+
+    fn ids(app: &App) -> Vec<i64> {
+        app.queue
+            .items
+            .iter()
+            .filter_map(|item| item.track.id)
+            .collect()
+    }
+
+    // ids of songs whose urls are asked to play
+    fn played(requests: &Receiver<Request>) -> Vec<i64> {
+        requests
+            .try_iter()
+            .filter_map(|request| match request {
+                Request::SongUrl(id, _, UrlFor::Play) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_repeat_off_stops_after_last_track() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut app, requests) = app(dir.path(), false);
+        app.repeat_state = RepeatState::Off;
+        app.play_list(vec![track(1), track(2)], 0, Source::Search);
+        app.skip_track(TrackState::Forword);
+        assert_eq!(played(&requests), vec![1, 2]);
+        assert_eq!(app.queue.current, Some(1));
+        // nothing is played after the last track
+        app.skip_track(TrackState::Forword);
+        assert!(played(&requests).is_empty());
+        assert_eq!(app.queue.current, Some(1));
+        assert_eq!(app.msg, "End of queue");
+        app.skip_track(TrackState::Backword);
+        assert_eq!(played(&requests), vec![1]);
+    }
+
+    #[test]
+    fn test_similar_songs_refill_before_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut app, requests) = app(dir.path(), true);
+        app.repeat_state = RepeatState::Off;
+        app.play_list(vec![track(1), track(2)], 0, Source::Search);
+        app.prefetch_next();
+        // not the last track yet
+        assert_eq!(app.similar, None);
+        app.skip_track(TrackState::Forword);
+        app.prefetch_next();
+        assert_eq!(app.similar, Some((2, false)));
+        assert!(requests
+            .try_iter()
+            .any(|request| matches!(request, Request::Similar(2))));
+        // songs already in queue are skipped
+        app.handle_resolved(Resolved::Similar(
+            2,
+            Ok(vec![track(1), track(3), track(2), track(4)]),
+        ));
+        assert_eq!(ids(&app), vec![1, 2, 3, 4]);
+        assert_eq!(app.queue.items[2].source, Source::Similar("song 2".to_owned()));
+        assert_eq!(app.queue.current, Some(1));
+        assert_eq!(app.prefetch.as_ref().map(|(index, _)| *index), Some(2));
+        app.skip_track(TrackState::Forword);
+        assert_eq!(played(&requests), vec![3]);
+        assert_eq!(app.queue.current, Some(2));
+    }
+
+    #[test]
+    fn test_skip_empty_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut app, requests) = app(dir.path(), true);
+        for repeat_state in [
+            RepeatState::Off,
+            RepeatState::Track,
+            RepeatState::All,
+            RepeatState::Shuffle,
+        ]
+        .iter()
+        {
+            app.repeat_state = repeat_state.to_owned();
+            app.queue.set_shuffle(app.repeat_state == RepeatState::Shuffle);
+            app.skip_track(TrackState::Forword);
+            app.skip_track(TrackState::Backword);
+            assert!(app.current_playing.is_none());
+            assert_eq!(app.queue.current, None);
+        }
+        assert!(requests.try_iter().next().is_none());
+        assert!(app.msg.is_empty());
+    }
+}
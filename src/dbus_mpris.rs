@@ -4,6 +4,8 @@
 // dbus-send --session --print-reply --dest=org.mpris.MediaPlayer2.ncmt /org/mpris/MediaPlayer2 org.freedesktop.DBus.Properties.Get string:"org.mpris.MediaPlayer2.Player" string:"Rate"
 // for method
 // dbus-send --session --print-reply --dest=org.mpris.MediaPlayer2.ncmt /org/mpris/MediaPlayer2 org.mpris.MediaPlayer2.Player.Next
+// for sleep timer, "30m", "track", "3 tracks" or "off"
+// dbus-send --session --print-reply --dest=org.mpris.MediaPlayer2.ncmt /org/mpris/MediaPlayer2 org.mpris.MediaPlayer2.Player.SetSleepTimer string:"30m"
 #[cfg(feature = "dbus_mpris")]
 extern crate dbus;
 use super::app::App;
@@ -15,14 +17,21 @@ use super::app::RepeatState;
 use super::handlers::TrackState;
 #[cfg(feature = "dbus_mpris")]
 use super::player::MetaInfo;
+#[cfg(feature = "dbus_mpris")]
+use super::player::Detail;
+#[cfg(feature = "dbus_mpris")]
+use super::player::Media;
+#[cfg(feature = "dbus_mpris")]
+use super::sleep::Sleep;
 use super::player::PlayerCommand;
 #[cfg(feature = "dbus_mpris")]
 use dbus::{
     blocking::Connection,
     arg::{RefArg, Variant, messageitem::MessageItem},
+    Path,
 };
 #[cfg(feature = "dbus_mpris")]
-use dbus::tree::{Access, Factory};
+use dbus::tree::{Access, Factory, MethodErr};
 use std::error::Error;
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
@@ -64,6 +73,12 @@ pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>
     Ok(())
 }
 
+// mpris track id is an object path, id of song is the last part
+#[cfg(feature = "dbus_mpris")]
+fn track_path(id: i64) -> Path<'static> {
+    Path::new(format!("/org/mpris/MediaPlayer2/Track/{}", id)).unwrap()
+}
+
 #[cfg(feature = "dbus_mpris")]
 pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>> {
     // Let's start by starting up a connection to the session bus and request a name.
@@ -197,9 +212,9 @@ pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>
     let method_seek = {
         let local_tx = tx.clone();
         f.method("Seek", (), move |m| {
-            // I change the Time in microseconds to the seconds.
-            let offset = m.msg.read1()?;
-            local_tx.send(PlayerCommand::Seek(offset)).unwrap();
+            // offset is in microseconds, player seeks in ms
+            let offset: i64 = m.msg.read1()?;
+            local_tx.send(PlayerCommand::Seek(offset / 1000)).unwrap();
             Ok(vec![m.msg.method_return()])
         })
     };
@@ -207,9 +222,18 @@ pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>
     let method_set_position = {
         let local_tx = tx.clone();
         f.method("SetPosition", (), move |m| {
-            let (track_id, position) = m.msg.read2()?;
+            let (track_id, position): (Path, i64) = m.msg.read2()?;
+            // position out of track is ignored by spec
+            if position < 0 {
+                return Ok(vec![m.msg.method_return()]);
+            }
+            let track_id = track_id
+                .rsplit('/')
+                .next()
+                .and_then(|id| id.parse::<i64>().ok())
+                .ok_or_else(|| MethodErr::invalid_arg(&track_id))?;
             local_tx
-                .send(PlayerCommand::Position(track_id, position))
+                .send(PlayerCommand::Position(track_id, position as u64 / 1000))
                 .unwrap();
             Ok(vec![m.msg.method_return()])
         })
@@ -219,7 +243,20 @@ pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>
         let local_tx = tx.clone();
         f.method("OpenUri", (), move |m| {
             let uri = m.msg.read1()?;
-            local_tx.send(PlayerCommand::Load(uri)).unwrap();
+            local_tx
+                .send(PlayerCommand::Load(Media::Url(uri), Detail::default()))
+                .unwrap();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // not in mpris spec
+    let method_set_sleep_timer = {
+        let local_tx = tx.clone();
+        f.method("SetSleepTimer", (), move |m| {
+            let text: &str = m.msg.read1()?;
+            let sleep = Sleep::parse(text).map_err(|e| MethodErr::invalid_arg(&e.to_string()))?;
+            local_tx.send(PlayerCommand::Sleep(sleep)).unwrap();
             Ok(vec![m.msg.method_return()])
         })
     };
@@ -410,9 +447,9 @@ pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>
                         match serde_json::from_str::<Track>(&r) {
                             Ok(current_playing) => {
                                 m.insert("mpris:trackid".to_string(), Variant(Box::new(
-                                    MessageItem::Int64(
+                                    MessageItem::ObjectPath(track_path(
                                        current_playing.id.unwrap().to_owned()
-                                    )) as Box<dyn RefArg>));
+                                    ))) as Box<dyn RefArg>));
                                 m.insert("mpris:length".to_string(), Variant(Box::new(
                                     MessageItem::Int64(
                                         i64::from(100) * 1000
@@ -472,6 +509,7 @@ pub fn dbus_mpris_server(tx: Sender<PlayerCommand>) -> Result<(), Box<dyn Error>
                         .add_m(method_seek)
                         .add_m(method_set_position)
                         .add_m(method_open_uri)
+                        .add_m(method_set_sleep_timer)
                         .add_p(property_rate)
                         .add_p(property_max_rate)
                         .add_p(property_min_rate)
@@ -530,13 +568,24 @@ pub fn dbus_mpris_handler(r: PlayerCommand, app: &mut App) {
         PlayerCommand::Seek(x) => {
             app.player.seek(x);
         }
-        PlayerCommand::Position(_track_id, position) => {
-            let position = position / 1000;
-            app.player.position(position);
+        PlayerCommand::Position(track_id, position) => {
+            // client asked for another track than playing
+            let playing = app.current_playing.as_ref().and_then(|t| t.id);
+            if playing == Some(track_id) {
+                app.player.position(position);
+            }
+        }
+        PlayerCommand::Load(media, detail) => {
+            app.player.load(media, detail);
         }
-        PlayerCommand::Load(uri) => {
-            app.player.play_url(&uri);
+        PlayerCommand::Sleep(sleep) => {
+            app.set_sleep(sleep);
         }
+        PlayerCommand::Volume(_)
+        | PlayerCommand::Preload(_, _, _)
+        | PlayerCommand::ClearPreload
+        | PlayerCommand::Device(_)
+        | PlayerCommand::Speed(_) => {}
         PlayerCommand::Metadata(info, tx) => {
             let msg = match info {
                 MetaInfo::LoopStatus => match app.repeat_state {
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = ".library.json";
+
+// downloaded songs in music dir, index of song id to file
+pub struct Library {
+    dir: PathBuf,
+    songs: HashMap<i64, PathBuf>,
+}
+
+impl Library {
+    pub fn new(dir: PathBuf) -> Library {
+        let songs = fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_str::<HashMap<i64, PathBuf>>(&data).ok())
+            .unwrap_or_default();
+        Library { dir, songs }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // file of downloaded song, none if it is removed by user
+    pub fn file(&self, id: i64) -> Option<PathBuf> {
+        self.songs
+            .get(&id)
+            .map(|file| self.dir.join(file))
+            .filter(|file| file.exists())
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.songs.contains_key(&id)
+    }
+
+    pub fn insert(&mut self, id: i64, file: &Path) {
+        let file = file.strip_prefix(&self.dir).unwrap_or(file);
+        self.songs.insert(id, file.to_path_buf());
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            error!("create music dir failed {}", e);
+            return;
+        }
+        match serde_json::to_string(&self.songs) {
+            Ok(data) => {
+                if let Err(e) = fs::write(self.dir.join(INDEX_FILE), data) {
+                    error!("save library index failed {}", e);
+                }
+            }
+            Err(e) => error!("serialize library index failed {}", e),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+mod library;
+mod tag;
+mod template;
+
+use super::api::CloudMusic;
+use super::model::playlist::Track;
+use super::player::{fetch_data, Progress};
+use super::settings::{Quality, Settings};
+use failure::err_msg;
+use futures::channel::oneshot;
+use library::Library;
+use tag::write_tag;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum DownloadStatus {
+    Queued,
+    Active,
+    Paused,
+    Finished,
+    Failed(String),
+}
+
+// song in download list
+pub struct DownloadItem {
+    // unique in list, a song can be cancelled and added again
+    key: usize,
+    pub id: i64,
+    pub name: String,
+    pub status: DownloadStatus,
+    track: Track,
+    // bitrate asked when added
+    br: u32,
+    // path without extension until finished, it's added by the downloaded format
+    file: PathBuf,
+    // extension written in template, used if api doesn't tell the format
+    extension: Option<String>,
+    progress: Arc<Progress>,
+    // download thread is alive, paused item keeps its thread
+    running: bool,
+}
+
+impl DownloadItem {
+    pub fn downloaded(&self) -> u64 {
+        self.progress.downloaded()
+    }
+
+    pub fn total(&self) -> Option<u64> {
+        self.progress.total()
+    }
+}
+
+// events send from download threads by item key
+pub enum DownloadEvent {
+    // file extension follows the downloaded format
+    Finished(usize, PathBuf),
+    Failed(usize, String),
+}
+
+// save songs to music dir for offline playing
+pub struct Downloader {
+    pub items: Vec<DownloadItem>,
+    pub selected_index: usize,
+    events_tx: Sender<DownloadEvent>,
+    events: Receiver<DownloadEvent>,
+    library: Library,
+    // file path of track in music dir
+    template: String,
+    pub quality: Quality,
+    max_concurrent: usize,
+    next_key: usize,
+}
+
+impl Downloader {
+    pub fn new(settings: &Settings) -> Downloader {
+        let (events_tx, events) = mpsc::channel();
+        Downloader {
+            items: vec![],
+            selected_index: 0,
+            events_tx,
+            events,
+            library: Library::new(settings.music_dir.to_owned()),
+            template: settings.file_template.to_owned(),
+            quality: settings.quality,
+            max_concurrent: settings.max_downloads.max(1),
+            next_key: 0,
+        }
+    }
+
+    // add tracks to download, return the count of new ones
+    // failed ones in list are retried
+    pub fn download(&mut self, tracks: &[Track]) -> usize {
+        let mut count = 0;
+        for track in tracks {
+            let id = match track.id {
+                Some(id) => id,
+                None => continue,
+            };
+            if self.library.file(id).is_some() {
+                continue;
+            }
+            match self.items.iter().position(|item| item.id == id) {
+                Some(index) => {
+                    if let DownloadStatus::Failed(_) = self.items[index].status {
+                        self.retry(index);
+                        count += 1;
+                    }
+                }
+                None => {
+                    let (template, extension) = template::split_extension(&self.template);
+                    self.items.push(DownloadItem {
+                        key: self.next_key,
+                        id,
+                        name: format!(
+                            "{} - {}",
+                            template::artist_string(track),
+                            track.name.to_owned().unwrap_or_default()
+                        ),
+                        status: DownloadStatus::Queued,
+                        track: track.to_owned(),
+                        br: self.quality.bitrate(),
+                        file: self.library.dir().join(template::render(template, track)),
+                        extension: extension.map(str::to_owned),
+                        progress: Arc::new(Progress::default()),
+                        running: false,
+                    });
+                    self.next_key += 1;
+                    count += 1;
+                }
+            }
+        }
+        self.schedule();
+        count
+    }
+
+    // receive events from download threads and start queued items
+    pub fn poll_events(&mut self) -> Vec<DownloadEvent> {
+        let events = self.events.try_iter().collect::<Vec<DownloadEvent>>();
+        for event in events.iter() {
+            let (key, status) = match event {
+                DownloadEvent::Finished(key, _) => (key, DownloadStatus::Finished),
+                DownloadEvent::Failed(key, e) => (key, DownloadStatus::Failed(e.to_owned())),
+            };
+            // cancelled item is removed from list already
+            if let Some(item) = self.items.iter_mut().find(|item| item.key == *key) {
+                item.running = false;
+                item.status = status;
+                if let DownloadEvent::Finished(_, file) = event {
+                    item.file = file.to_owned();
+                    self.library.insert(item.id, file);
+                }
+            }
+        }
+        self.schedule();
+        events
+    }
+
+    pub fn pause(&mut self, index: usize) {
+        if let Some(item) = self.items.get_mut(index) {
+            if item.status == DownloadStatus::Queued || item.status == DownloadStatus::Active {
+                item.progress.set_paused(true);
+                item.status = DownloadStatus::Paused;
+            }
+        }
+        self.schedule();
+    }
+
+    pub fn resume(&mut self, index: usize) {
+        if let Some(item) = self.items.get_mut(index) {
+            if item.status == DownloadStatus::Paused {
+                item.progress.set_paused(false);
+                item.status = if item.running {
+                    DownloadStatus::Active
+                } else {
+                    DownloadStatus::Queued
+                };
+            }
+        }
+        self.schedule();
+    }
+
+    // remove item from list, its thread stops at next chunk
+    pub fn cancel(&mut self, index: usize) {
+        if index < self.items.len() {
+            let item = self.items.remove(index);
+            item.progress.cancel();
+            if self.selected_index >= self.items.len() && self.selected_index > 0 {
+                self.selected_index = self.items.len() - 1;
+            }
+        }
+        self.schedule();
+    }
+
+    // download failed item again from start
+    pub fn retry(&mut self, index: usize) {
+        if let Some(item) = self.items.get_mut(index) {
+            if let DownloadStatus::Failed(_) = item.status {
+                item.progress = Arc::new(Progress::default());
+                item.status = DownloadStatus::Queued;
+            }
+        }
+        self.schedule();
+    }
+
+    pub fn is_downloaded(&self, id: i64) -> bool {
+        self.library.contains(id)
+    }
+
+    // local file of downloaded song
+    pub fn file(&self, id: i64) -> Option<PathBuf> {
+        self.library.file(id)
+    }
+
+    // start queued items until max concurrent downloads
+    // paused items keep their thread and connection, so they take a place too
+    fn schedule(&mut self) {
+        let mut active = self.items.iter().filter(|item| item.running).count();
+        for item in self.items.iter_mut() {
+            if active >= self.max_concurrent {
+                break;
+            }
+            if item.status == DownloadStatus::Queued {
+                Downloader::start(item, self.events_tx.clone());
+                active += 1;
+            }
+        }
+    }
+
+    fn start(item: &mut DownloadItem, events: Sender<DownloadEvent>) {
+        item.status = DownloadStatus::Active;
+        item.running = true;
+        let key = item.key;
+        let track = item.track.to_owned();
+        let br = item.br;
+        let file = item.file.to_owned();
+        let extension = item.extension.to_owned();
+        let progress = item.progress.clone();
+        thread::spawn(move || {
+            let api = CloudMusic::default();
+            let event = match Downloader::save(&api, &track, br, &file, extension, progress) {
+                Ok(file) => DownloadEvent::Finished(key, file),
+                Err(e) => {
+                    error!("download song {:?} failed {}", track.id, e);
+                    DownloadEvent::Failed(key, e.to_string())
+                }
+            };
+            events.send(event).ok();
+        });
+    }
+
+    // download into a part file and rename it when finished
+    fn save(
+        api: &CloudMusic,
+        track: &Track,
+        br: u32,
+        file: &Path,
+        extension: Option<String>,
+        progress: Arc<Progress>,
+    ) -> Result<PathBuf, failure::Error> {
+        let id = track.id.ok_or_else(|| err_msg("track has no id"))?;
+        let song = api.get_song_url(&id.to_string(), br)?;
+        let url = song.url.ok_or_else(|| err_msg("get track url failed"))?;
+        let extension = song.format.map(|format| format.to_lowercase()).or(extension);
+        let file = match extension {
+            Some(extension) => template::append_extension(file, &extension),
+            None => file.to_path_buf(),
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let part = template::append_extension(&file, "part");
+        // fetch_data reports the first chunk, keep the receiver until finished
+        let (tx, _rx) = oneshot::channel::<String>();
+        let result = fetch_data(&url, fs::File::create(&part)?, tx, progress);
+        if result.is_err() {
+            fs::remove_file(&part).ok();
+        }
+        result?;
+        if file.extension().is_some_and(|ext| ext == "mp3") {
+            // song without tag is still playable
+            if let Err(e) = write_tag(api, track, &part) {
+                error!("write tag of {} failed {}", file.display(), e);
+            }
+        }
+        fs::rename(&part, &file)?;
+        Ok(file)
+    }
+}
@@ -0,0 +1,88 @@
+use super::super::api::{CloudMusic, CLIENT};
+use super::super::model::playlist::Track;
+use super::template::artist_string;
+use id3::frame::{Lyrics, Picture, PictureType};
+use id3::{Tag, TagLike, Version};
+use reqwest::header::CONTENT_TYPE;
+use std::io::Read;
+use std::path::Path;
+
+// write id3 tag of track info, cover and lyric to mp3 file
+pub fn write_tag(api: &CloudMusic, track: &Track, file: &Path) -> Result<(), failure::Error> {
+    let mut tag = Tag::new();
+    if let Some(name) = &track.name {
+        tag.set_title(name.to_owned());
+    }
+    let artist = artist_string(track);
+    if !artist.is_empty() {
+        tag.set_artist(artist);
+    }
+    if let Some(album) = &track.album {
+        if let Some(name) = &album.name {
+            tag.set_album(name.to_owned());
+        }
+        if let Some(url) = &album.picUrl {
+            match cover(url) {
+                Ok((data, mime_type)) => {
+                    tag.add_frame(Picture {
+                        mime_type,
+                        picture_type: PictureType::CoverFront,
+                        description: String::new(),
+                        data,
+                    });
+                }
+                Err(e) => error!("download cover failed {}", e),
+            }
+        }
+    }
+    if let Some(no) = track.no.filter(|no| *no > 0) {
+        tag.set_track(no as u32);
+    }
+    if let Some(id) = track.id {
+        match api.lyric(&id.to_string()) {
+            Ok(lines) => {
+                let lyric = lines
+                    .into_iter()
+                    .map(|line| line.value)
+                    .collect::<Vec<String>>();
+                // api gives this line if the track has no lyric
+                if lyric != ["no lyric"] {
+                    tag.add_frame(Lyrics {
+                        lang: "und".to_owned(),
+                        description: String::new(),
+                        text: lyric.join("\n").trim().to_owned(),
+                    });
+                }
+            }
+            Err(e) => error!("get lyric failed {}", e),
+        }
+    }
+    tag.write_to_path(file, Version::Id3v23)?;
+    Ok(())
+}
+
+// cover data and its mime type, from the response or the magic bytes
+fn cover(url: &str) -> Result<(Vec<u8>, String), failure::Error> {
+    let mut response = CLIENT.get(url).send()?;
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| value.starts_with("image/"));
+    let mut data = vec![];
+    response.read_to_end(&mut data)?;
+    let mime_type = content_type.unwrap_or_else(|| image_type(&data).to_owned());
+    Ok((data, mime_type))
+}
+
+fn image_type(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    }
+}
@@ -0,0 +1,154 @@
+use super::super::model::playlist::Track;
+use std::path::{Path, PathBuf};
+
+// names in template may not have path separator or chars invalid on windows
+fn clean(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_start_matches('.')
+        .to_owned()
+}
+
+pub fn artist_string(track: &Track) -> String {
+    track
+        .artists
+        .to_owned()
+        .unwrap_or_default()
+        .iter()
+        .map(|artist| artist.name.to_owned())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// file path of track in music dir
+// {artist}, {album}, {no} and {title} in template are replaced by track info
+pub fn render(template: &str, track: &Track) -> PathBuf {
+    let artist = artist_string(track);
+    let album = track
+        .album
+        .as_ref()
+        .and_then(|album| album.name.to_owned())
+        .unwrap_or_default();
+    let no = track
+        .no
+        .filter(|no| *no > 0)
+        .map(|no| format!("{:02}", no))
+        .unwrap_or_default();
+    let title = track.name.to_owned().unwrap_or_default();
+    let fields = [
+        ("{artist}", or_unknown(&artist, "Unknown Artist")),
+        ("{album}", or_unknown(&album, "Unknown Album")),
+        ("{no}", no),
+        ("{title}", or_unknown(&title, "Unknown Title")),
+    ];
+    template
+        .split('/')
+        .map(|part| {
+            let name = fields
+                .iter()
+                .fold(part.to_owned(), |name, (key, value)| {
+                    name.replace(key, &clean(value))
+                });
+            // "{no} - {title}" without number
+            clean(name.trim_start_matches(" - "))
+        })
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+// template without the extension written at its end and the extension,
+// names replaced in template may have dots
+pub fn split_extension(template: &str) -> (&str, Option<&str>) {
+    let name = template.rsplit('/').next().unwrap_or(template);
+    match name.rfind('.') {
+        Some(dot) => {
+            let extension = &name[dot + 1..];
+            if !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+                (&template[..template.len() - extension.len() - 1], Some(extension))
+            } else {
+                (template, None)
+            }
+        }
+        None => (template, None),
+    }
+}
+
+// file name with extension appended, with_extension would cut a name with dots
+pub fn append_extension(file: &Path, extension: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn or_unknown(value: &str, unknown: &str) -> String {
+    if value.trim().is_empty() {
+        unknown.to_owned()
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::model::album::Album;
+    use super::super::super::model::artist::Artist;
+    use super::*;
+
+    fn track(no: Option<i32>) -> Track {
+        Track {
+            name: Some("Song: One".to_owned()),
+            id: Some(1),
+            artists: Some(vec![
+                Artist {
+                    id: 1,
+                    name: "A/B".to_owned(),
+                    alias: None,
+                },
+                Artist {
+                    id: 2,
+                    name: "C".to_owned(),
+                    alias: None,
+                },
+            ]),
+            album: Some(Album {
+                id: Some(1),
+                name: Some("Album".to_owned()),
+                size: None,
+                artist: None,
+                picUrl: None,
+            }),
+            no,
+            duration: None,
+            radio: None,
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template = "{artist}/{album}/{no} - {title}.mp3";
+        assert_eq!(
+            render(template, &track(Some(3))),
+            PathBuf::from("A_B, C/Album/03 - Song_ One.mp3")
+        );
+        assert_eq!(
+            render(template, &track(None)),
+            PathBuf::from("A_B, C/Album/Song_ One.mp3")
+        );
+        assert_eq!(
+            render("../{title}.mp3", &track(None)),
+            PathBuf::from("Song_ One.mp3")
+        );
+        assert_eq!(split_extension(template), ("{artist}/{album}/{no} - {title}", Some("mp3")));
+        assert_eq!(split_extension("{no}. {title}"), ("{no}. {title}", None));
+        assert_eq!(
+            append_extension(Path::new("A/Mr. Song"), "flac"),
+            PathBuf::from("A/Mr. Song.flac")
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use super::super::app::{App, TrackTable};
+use super::super::app::App;
+use super::super::queue::Source;
 use super::common_events;
 use termion::event::Key;
 
@@ -24,21 +25,32 @@ pub fn handler(key: Key, app: &mut App) {
             }
         }
         Key::Char('\n') => {
-            if let Some(selected_album) = &mut app.selected_album {
-                app.my_playlist = TrackTable {
-                    tracks: selected_album.tracks.to_owned(),
-                    selected_index: selected_album.selected_index,
-                    name: selected_album.album.name.to_owned().unwrap(),
-                };
-                if let Some(selected_track) = selected_album
+            if let Some(selected_album) = app.selected_album.to_owned() {
+                app.play_list(
+                    selected_album.tracks,
+                    selected_album.selected_index,
+                    Source::Album(selected_album.album.name.unwrap_or_default()),
+                );
+            };
+        }
+        k if common_events::queue_event(k) => {
+            let track = app.selected_album.as_ref().and_then(|selected_album| {
+                let source =
+                    Source::Album(selected_album.album.name.to_owned().unwrap_or_default());
+                selected_album
                     .tracks
                     .get(selected_album.selected_index)
-                    .cloned()
-                {
-                    app.start_playback(selected_track);
-                    app.fm_state = false;
-                }
-            };
+                    .map(|track| (track.to_owned(), source))
+            });
+            if let Some((track, source)) = track {
+                common_events::handle_queue_event(k, app, track, source);
+            }
+        }
+        Key::Char('D') => {
+            if let Some(selected_album) = &app.selected_album {
+                let tracks = selected_album.tracks.to_owned();
+                app.download_tracks(&tracks);
+            }
         }
         _ => {}
     }
@@ -1,4 +1,6 @@
 use super::super::app::{ActiveBlock, App, RouteId};
+use super::super::model::playlist::Track;
+use super::super::queue::Source;
 use termion::event::Key;
 
 pub fn down_event(key: Key) -> bool {
@@ -29,6 +31,23 @@ pub fn right_event(key: Key) -> bool {
     }
 }
 
+// play now, play next or append to queue
+pub fn queue_event(key: Key) -> bool {
+    match key {
+        Key::Char('P') | Key::Char('N') | Key::Char('e') => true,
+        _ => false,
+    }
+}
+
+pub fn handle_queue_event(key: Key, app: &mut App, track: Track, source: Source) {
+    match key {
+        Key::Char('P') => app.play_now(track, source),
+        Key::Char('N') => app.play_next(track, source),
+        Key::Char('e') => app.append_tracks(vec![track], source),
+        _ => {}
+    }
+}
+
 pub fn on_down_press_handler<T>(selection_data: &[T], selection_index: Option<usize>) -> usize {
     match selection_index {
         Some(selection_index) => {
@@ -118,6 +137,18 @@ pub fn handle_right_event(app: &mut App) {
             RouteId::DjProgram => {
                 app.set_current_route_state(Some(ActiveBlock::Empty), Some(ActiveBlock::DjProgram));
             }
+            RouteId::Downloads => {
+                app.set_current_route_state(Some(ActiveBlock::Empty), Some(ActiveBlock::Downloads));
+            }
+            RouteId::Devices => {
+                app.set_current_route_state(Some(ActiveBlock::Empty), Some(ActiveBlock::Devices));
+            }
+            RouteId::Alarms => {
+                app.set_current_route_state(Some(ActiveBlock::Empty), Some(ActiveBlock::Alarms));
+            }
+            RouteId::Queue => {
+                app.set_current_route_state(Some(ActiveBlock::Empty), Some(ActiveBlock::Queue));
+            }
             RouteId::Home => {
                 app.set_current_route_state(Some(ActiveBlock::Empty), Some(ActiveBlock::Home));
             }
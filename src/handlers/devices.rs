@@ -0,0 +1,25 @@
+use super::super::app::App;
+use super::common_events;
+use termion::event::Key;
+
+pub fn handler(key: Key, app: &mut App) {
+    match key {
+        k if common_events::left_event(k) => common_events::handle_left_event(app),
+        k if common_events::down_event(k) => {
+            let next_index = common_events::on_down_press_handler(
+                &app.device_table.devices,
+                Some(app.device_table.selected_index),
+            );
+            app.device_table.selected_index = next_index;
+        }
+        k if common_events::up_event(k) => {
+            let next_index = common_events::on_up_press_handler(
+                &app.device_table.devices,
+                Some(app.device_table.selected_index),
+            );
+            app.device_table.selected_index = next_index;
+        }
+        Key::Char('\n') => app.select_device(),
+        _ => {}
+    }
+}
@@ -1,6 +1,7 @@
-use super::super::app::{App, TrackTable};
+use super::super::app::{App, ProgramTable};
 use super::super::model::artist::Artist;
 use super::super::model::playlist::Track;
+use super::super::queue::Source;
 use super::common_events;
 use termion::event::Key;
 
@@ -26,36 +27,48 @@ pub fn handler(key: Key, app: &mut App) {
             }
         }
         Key::Char('\n') => {
-            if let Some(djprogram_list) = &app.program_list.clone() {
-                // convert djprogram to tracks
-                let track_list = djprogram_list
-                    .dj_programs
-                    .iter()
-                    .map(|item| {
-                        let artist = Artist {
-                            id: item.radio.id as i32,
-                            name: item.radio.name.to_string(),
-                            alias: None,
-                        };
-                        Track {
-                            name: Some(item.mainSong.name.to_string()),
-                            id: Some(item.mainSong.id as i64),
-                            artists: Some(vec![artist]),
-                            album: None,
-                        }
-                    })
-                    .collect::<Vec<Track>>();
-                if let Some(djprogram) = track_list.get(djprogram_list.selected_index.to_owned()) {
-                    app.my_playlist = TrackTable {
-                        tracks: track_list.to_owned(),
-                        selected_index: djprogram_list.selected_index,
-                        name: "dj program".to_string(),
-                    };
-                    app.start_playback(djprogram.to_owned());
-                    app.fm_state = false;
-                }
+            if let Some(djprogram_list) = &app.program_list {
+                let index = djprogram_list.selected_index;
+                let track_list = program_tracks(djprogram_list);
+                let source = Source::DjProgram(djprogram_list.name.to_owned());
+                app.play_list(track_list, index, source);
             };
         }
+        k if common_events::queue_event(k) => {
+            let track = app.program_list.as_ref().and_then(|djprogram_list| {
+                let source = Source::DjProgram(djprogram_list.name.to_owned());
+                program_tracks(djprogram_list)
+                    .get(djprogram_list.selected_index)
+                    .map(|track| (track.to_owned(), source))
+            });
+            if let Some((track, source)) = track {
+                common_events::handle_queue_event(k, app, track, source);
+            }
+        }
         _ => {}
     }
 }
+
+// convert djprogram to tracks
+fn program_tracks(djprogram_list: &ProgramTable) -> Vec<Track> {
+    djprogram_list
+        .dj_programs
+        .iter()
+        .map(|item| {
+            let artist = Artist {
+                id: item.radio.id as i32,
+                name: item.radio.name.to_string(),
+                alias: None,
+            };
+            Track {
+                name: Some(item.mainSong.name.to_string()),
+                id: Some(item.mainSong.id as i64),
+                artists: Some(vec![artist]),
+                album: None,
+                no: None,
+                duration: Some(item.mainSong.duration as u64),
+                radio: Some(item.radio.id as i64),
+            }
+        })
+        .collect()
+}
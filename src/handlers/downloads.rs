@@ -0,0 +1,42 @@
+use super::super::app::App;
+use super::super::download::DownloadStatus;
+use super::common_events;
+use termion::event::Key;
+
+pub fn handler(key: Key, app: &mut App) {
+    match key {
+        k if common_events::left_event(k) => common_events::handle_left_event(app),
+        k if common_events::down_event(k) => {
+            let next_index = common_events::on_down_press_handler(
+                &app.downloader.items,
+                Some(app.downloader.selected_index),
+            );
+            app.downloader.selected_index = next_index;
+        }
+        k if common_events::up_event(k) => {
+            let next_index = common_events::on_up_press_handler(
+                &app.downloader.items,
+                Some(app.downloader.selected_index),
+            );
+            app.downloader.selected_index = next_index;
+        }
+        Key::Char('\n') => {
+            let index = app.downloader.selected_index;
+            if let Some(item) = app.downloader.items.get(index) {
+                match item.status {
+                    DownloadStatus::Paused => app.downloader.resume(index),
+                    _ => app.downloader.pause(index),
+                }
+            }
+        }
+        Key::Char('c') => {
+            let index = app.downloader.selected_index;
+            app.downloader.cancel(index);
+        }
+        Key::Char('R') => {
+            let index = app.downloader.selected_index;
+            app.downloader.retry(index);
+        }
+        _ => {}
+    }
+}
@@ -19,6 +19,10 @@ pub fn handler(key: Key, app: &mut App) {
             | ActiveBlock::Playing
             | ActiveBlock::DjRadio
             | ActiveBlock::DjProgram
+            | ActiveBlock::Downloads
+            | ActiveBlock::Devices
+            | ActiveBlock::Alarms
+            | ActiveBlock::Queue
             | ActiveBlock::TrackTable => {
                 app.set_current_route_state(None, Some(ActiveBlock::Recommend));
             }
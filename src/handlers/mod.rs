@@ -1,15 +1,19 @@
+mod alarms;
 mod album_tracks;
 mod albumlist;
 mod artist;
 mod artistlist;
 mod common_events;
+mod devices;
 mod djprogram;
 mod djradio;
+mod downloads;
 mod empty;
 mod fm;
 mod home;
 mod my_playlist;
 mod playlist;
+mod queue;
 mod recommend;
 mod search;
 mod search_results;
@@ -55,6 +59,12 @@ pub fn handle_app(key: Key, app: &mut App) {
             Key::Char('r') => {
                 app.repeat();
             }
+            Key::Char('b') => {
+                app.toggle_quality();
+            }
+            Key::Char('A') => {
+                app.toggle_play_similar();
+            }
             Key::Char('?') => {
                 app.set_current_route_state(Some(ActiveBlock::Help), None);
             }
@@ -73,6 +83,18 @@ pub fn handle_app(key: Key, app: &mut App) {
             Key::Char('<') => {
                 app.player.seek_backwards();
             }
+            Key::Char(']') => {
+                app.change_speed(true);
+            }
+            Key::Char('[') => {
+                app.change_speed(false);
+            }
+            Key::Char('z') => {
+                app.next_sleep(false);
+            }
+            Key::Char('Z') => {
+                app.next_sleep(true);
+            }
             Key::Esc => {
                 app.hover_mode();
             }
@@ -145,6 +167,18 @@ fn handle_block_events(key: Key, app: &mut App) {
         ActiveBlock::DjProgram => {
             djprogram::handler(key, app);
         }
+        ActiveBlock::Downloads => {
+            downloads::handler(key, app);
+        }
+        ActiveBlock::Devices => {
+            devices::handler(key, app);
+        }
+        ActiveBlock::Alarms => {
+            alarms::handler(key, app);
+        }
+        ActiveBlock::Queue => {
+            queue::handler(key, app);
+        }
         _ => {}
     }
 }
@@ -37,6 +37,16 @@ pub fn handler(key: Key, app: &mut App) {
                 }
             };
         }
+        Key::Char('D') => {
+            if let (Some(playlists), Some(selected_playlist_index)) =
+                (&app.playlists, &app.selected_playlist_index)
+            {
+                if let Some(selected_playlist) = playlists.get(selected_playlist_index.to_owned()) {
+                    let playlist_id = selected_playlist.id.to_owned().unwrap();
+                    app.download_playlist(playlist_id.to_string());
+                }
+            };
+        }
         _ => {}
     }
 }
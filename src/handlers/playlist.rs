@@ -32,6 +32,15 @@ pub fn handler(key: Key, app: &mut App) {
                 }
             };
         }
+        Key::Char('D') => {
+            if let Some(playlists) = &app.playlist_list {
+                if let Some(playlist) = playlists.playlists.get(playlists.selected_index.to_owned())
+                {
+                    let playlist_id = playlist.id.to_owned().unwrap();
+                    app.download_playlist(playlist_id.to_string());
+                }
+            };
+        }
         Key::Ctrl('f') => {
             let limit = (app.block_height - 4) as i32;
             if let Some(playlists) = &app.playlist_list {
@@ -0,0 +1,29 @@
+use super::super::app::App;
+use super::common_events;
+use termion::event::Key;
+
+pub fn handler(key: Key, app: &mut App) {
+    match key {
+        k if common_events::left_event(k) => common_events::handle_left_event(app),
+        k if common_events::down_event(k) => {
+            let next_index = common_events::on_down_press_handler(
+                &app.queue.items,
+                Some(app.queue.selected_index),
+            );
+            app.queue.selected_index = next_index;
+        }
+        k if common_events::up_event(k) => {
+            let next_index = common_events::on_up_press_handler(
+                &app.queue.items,
+                Some(app.queue.selected_index),
+            );
+            app.queue.selected_index = next_index;
+        }
+        Key::Char('\n') => app.play_queue_selected(),
+        Key::Char('x') => app.remove_from_queue(),
+        Key::Char('K') => app.move_in_queue(true),
+        Key::Char('J') => app.move_in_queue(false),
+        Key::Char('C') => app.clear_queue(),
+        _ => {}
+    }
+}
@@ -31,7 +31,9 @@ pub fn handler(key: Key, app: &mut App) {
                     app.get_top_playlist(limit, 0);
                     app.push_navigation_stack(RouteId::Playlist, ActiveBlock::Playlist)
                 }
-                2 => app.set_fm_mode(),
+                2 => {
+                    app.set_fm_mode();
+                }
                 3 => {
                     app.get_top_albums(limit, 0);
                     app.push_navigation_stack(RouteId::AlbumList, ActiveBlock::AlbumList);
@@ -44,6 +46,17 @@ pub fn handler(key: Key, app: &mut App) {
                     app.get_sub_dj_radio(limit, 0);
                     app.push_navigation_stack(RouteId::DjRadio, ActiveBlock::DjRadio);
                 }
+                6 => app.push_navigation_stack(RouteId::Downloads, ActiveBlock::Downloads),
+                7 => {
+                    app.list_devices();
+                    app.push_navigation_stack(RouteId::Devices, ActiveBlock::Devices);
+                }
+                8 => app.push_navigation_stack(RouteId::Alarms, ActiveBlock::Alarms),
+                9 => {
+                    // start at the playing track
+                    app.queue.selected_index = app.queue.current.unwrap_or(0);
+                    app.push_navigation_stack(RouteId::Queue, ActiveBlock::Queue);
+                }
                 _ => {}
             }
         }
@@ -1,4 +1,5 @@
-use super::super::app::{ActiveBlock, App, RouteId, TrackTable};
+use super::super::app::{ActiveBlock, App, RouteId};
+use super::super::queue::Source;
 use super::common_events;
 use termion::event::Key;
 
@@ -87,19 +88,28 @@ pub fn handler(key: Key, app: &mut App) {
             };
             app.tabs.index = next;
         }
+        k if app.tabs.index == 0 && common_events::queue_event(k) => {
+            let index = app.search_results.selected_tracks_index;
+            let track = app
+                .search_results
+                .tracks
+                .as_ref()
+                .and_then(|tracks| tracks.get(index).cloned());
+            if let Some(track) = track {
+                common_events::handle_queue_event(k, app, track, Source::Search);
+            }
+        }
         Key::Char('\n') => {
             if app.tabs.index == 0 {
                 match &app.search_results.tracks.clone() {
                     Some(tracks) => {
                         match tracks.get(app.search_results.selected_tracks_index.to_owned()) {
-                            Some(track_playing) => {
-                                app.start_playback(track_playing.to_owned());
-                                app.fm_state = false;
-                                app.my_playlist = TrackTable {
-                                    tracks: app.search_results.tracks.to_owned().unwrap(),
-                                    selected_index: app.search_results.selected_tracks_index,
-                                    name: "search result".to_string(),
-                                };
+                            Some(_) => {
+                                app.play_list(
+                                    tracks.to_owned(),
+                                    app.search_results.selected_tracks_index,
+                                    Source::Search,
+                                );
                             }
                             None => {}
                         }
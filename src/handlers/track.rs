@@ -1,4 +1,5 @@
 use super::super::app::App;
+use super::super::queue::Source;
 use super::common_events;
 use termion::event::Key;
 
@@ -20,17 +21,23 @@ pub fn handler(key: Key, app: &mut App) {
             app.track_table.selected_index = next_index;
         }
         Key::Char('\n') => {
-            #[allow(non_snake_case)]
-            let TrackTable = &app.track_table;
-            let track_playing = TrackTable
-                .tracks
-                .get(TrackTable.selected_index.to_owned())
-                .unwrap()
-                .to_owned();
-            app.my_playlist = TrackTable.to_owned();
-
-            app.start_playback(track_playing);
-            app.fm_state = false;
+            let track_table = app.track_table.to_owned();
+            app.play_list(
+                track_table.tracks,
+                track_table.selected_index,
+                Source::Playlist(track_table.name),
+            );
+        }
+        k if common_events::queue_event(k) => {
+            let index = app.track_table.selected_index;
+            if let Some(track) = app.track_table.tracks.get(index).cloned() {
+                let source = Source::Playlist(app.track_table.name.to_owned());
+                common_events::handle_queue_event(k, app, track, source);
+            }
+        }
+        Key::Char('D') => {
+            let tracks = app.track_table.tracks.to_owned();
+            app.download_tracks(&tracks);
         }
         _ => {}
     }
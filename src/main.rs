@@ -22,17 +22,24 @@ use tui::backend::TermionBackend;
 use tui::Terminal;
 use util::event::{Event, Events};
 
+mod alarm;
 mod api;
 mod app;
+mod download;
 mod handlers;
 mod model;
 mod player;
+mod queue;
+mod resolve;
+mod settings;
+mod sleep;
 mod ui;
 mod util;
 
 mod dbus_mpris;
 
 use app::{ActiveBlock, App};
+use settings::Settings;
 
 use dbus_mpris::{dbus_mpris_handler, DbusMpris};
 
@@ -86,7 +93,11 @@ fn main() -> Result<(), failure::Error> {
     info!("start netease cloud music rust client");
 
     // init application
-    let mut app = App::new();
+    let mut app_settings = Settings::new(&settings);
+    if let Some(backend) = backend_arg() {
+        app_settings.backend = backend;
+    }
+    let mut app = App::new(app_settings);
     let mut is_first_render = true;
 
     let cloud_music = app.cloud_music.to_owned().unwrap();
@@ -191,3 +202,17 @@ fn main() -> Result<(), failure::Error> {
     }
     Ok(())
 }
+
+// audio backend by --backend <name> overrides the config
+fn backend_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--backend" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--backend=") {
+            return Some(name.to_owned());
+        }
+    }
+    None
+}
@@ -50,6 +50,12 @@ pub struct Track {
     pub id: Option<i64>,
     pub artists: Option<Vec<Artist>>,
     pub album: Option<Album>,
+    // track number in album
+    pub no: Option<i32>,
+    // duration in ms
+    pub duration: Option<u64>,
+    // dj radio of program, none for songs
+    pub radio: Option<i64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -58,6 +64,9 @@ pub struct PlaylistTrack {
     pub id: Option<i64>,
     pub ar: Option<Vec<Artist>>,
     pub al: Option<Album>,
+    pub no: Option<i32>,
+    // duration in ms
+    pub dt: Option<u64>,
 }
 
 impl PartialEq for Track {
@@ -68,6 +77,12 @@ impl PartialEq for Track {
 
 impl Eq for Track {}
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimiSongRes {
+    pub songs: Vec<Track>,
+    pub code: Option<i32>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PersonalFmRes {
     pub data: Vec<Track>,
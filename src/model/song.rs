@@ -11,5 +11,8 @@ pub struct Songs {
 pub struct Song {
     pub id: Option<i32>,
     pub url: Option<String>,
-    // pub br: Option<i32>,
+    pub br: Option<i32>,
+    // mp3 or flac
+    #[serde(rename = "type")]
+    pub format: Option<String>,
 }
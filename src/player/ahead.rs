@@ -0,0 +1,283 @@
+use rodio::Source;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// samples sent from decode thread at once
+const CHUNK: usize = 4096;
+// chunks decoded before they are played
+const AHEAD: usize = 8;
+// silence played at once while the decoder waits for download
+const SILENCE: Duration = Duration::from_millis(10);
+
+// decoded samples of one format
+struct Chunk {
+    samples: Vec<i16>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+struct Current {
+    chunk: Chunk,
+    position: usize,
+}
+
+// decode source in its own thread, a read waiting for download holds that thread
+// instead of the audio output, which takes only what is decoded already
+pub fn decode<S>(mut source: S) -> Chunks
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    let (tx, rx) = mpsc::sync_channel(AHEAD);
+    let first = Chunk {
+        samples: vec![],
+        channels: source.channels(),
+        sample_rate: source.sample_rate(),
+    };
+    thread::spawn(move || loop {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        // a chunk doesn't cross a frame, whose format may change
+        let whole = CHUNK / usize::from(channels.max(1)) * usize::from(channels.max(1));
+        let len = source.current_frame_len().map_or(whole, |len| len.min(whole));
+        let samples: Vec<i16> = source.by_ref().take(len).collect();
+        // the source is at the end or the output is dropped
+        if samples.is_empty() {
+            break;
+        }
+        let chunk = Chunk {
+            samples,
+            channels,
+            sample_rate,
+        };
+        if tx.send(chunk).is_err() {
+            break;
+        }
+    });
+    Chunks {
+        chunks: rx,
+        current: RefCell::new(Current {
+            chunk: first,
+            position: 0,
+        }),
+        ended: Arc::new(AtomicBool::new(false)),
+    }
+}
+
+// samples of decode thread, none while it's behind the output
+pub struct Chunks {
+    chunks: Receiver<Chunk>,
+    current: RefCell<Current>,
+    ended: Arc<AtomicBool>,
+}
+
+impl Chunks {
+    // set when the decode thread finished and all chunks are played
+    pub fn ended(&self) -> Arc<AtomicBool> {
+        self.ended.clone()
+    }
+
+    // take next chunk after current one is played, also done by frame len
+    // so the format of next chunk is known before its first sample
+    fn poll(&self) {
+        let mut current = self.current.borrow_mut();
+        if current.position < current.chunk.samples.len() {
+            return;
+        }
+        match self.chunks.try_recv() {
+            Ok(chunk) => {
+                *current = Current { chunk, position: 0 };
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.ended.store(true, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Iterator for Chunks {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        self.poll();
+        let current = self.current.get_mut();
+        let sample = current.chunk.samples.get(current.position).copied();
+        if sample.is_some() {
+            current.position += 1;
+        }
+        sample
+    }
+}
+
+impl Source for Chunks {
+    // 0 while decoder is behind or at the end
+    fn current_frame_len(&self) -> Option<usize> {
+        self.poll();
+        let current = self.current.borrow();
+        Some(current.chunk.samples.len() - current.position)
+    }
+
+    fn channels(&self) -> u16 {
+        self.current.borrow().chunk.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.current.borrow().chunk.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// source of chunks with silence where the decoder is behind, so the sink
+// doesn't take it as finished. the samples under it are counted before,
+// silence doesn't move the clock of track
+pub struct Fill<S> {
+    source: S,
+    ended: Arc<AtomicBool>,
+    // silent samples left, it's set by frame len too so the frame given
+    // to output is silence even if a chunk comes in the meantime
+    silence: Cell<usize>,
+}
+
+impl<S> Fill<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(source: S, ended: Arc<AtomicBool>) -> Fill<S> {
+        Fill {
+            source,
+            ended,
+            silence: Cell::new(0),
+        }
+    }
+
+    // whole frames of silence
+    fn block(&self) -> usize {
+        let frames = u64::from(self.source.sample_rate()) * SILENCE.as_millis() as u64 / 1000;
+        (frames.max(1) as usize) * usize::from(self.source.channels().max(1))
+    }
+}
+
+impl<S> Iterator for Fill<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let silence = self.silence.get();
+        if silence > 0 {
+            self.silence.set(silence - 1);
+            return Some(0);
+        }
+        match self.source.next() {
+            Some(sample) => Some(sample),
+            None if self.ended.load(Ordering::Relaxed) => None,
+            None => {
+                self.silence.set(self.block() - 1);
+                Some(0)
+            }
+        }
+    }
+}
+
+impl<S> Source for Fill<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.silence.get() == 0 {
+            match self.source.current_frame_len() {
+                Some(0) if !self.ended.load(Ordering::Relaxed) => self.silence.set(self.block()),
+                len => return len,
+            }
+        }
+        Some(self.silence.get())
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Sender;
+    use std::time::Instant;
+
+    // source reading samples from a channel, it waits like a file in download
+    struct Waiting(Receiver<i16>);
+
+    impl Iterator for Waiting {
+        type Item = i16;
+
+        fn next(&mut self) -> Option<i16> {
+            self.0.recv().ok()
+        }
+    }
+
+    impl Source for Waiting {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            1000
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    fn send(tx: &Sender<i16>, count: usize) {
+        for _ in 0..count {
+            tx.send(1).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_silence_while_decoder_waits() {
+        let (tx, rx) = mpsc::channel();
+        let chunks = decode(Waiting(rx));
+        let ended = chunks.ended();
+        let mut source = Fill::new(chunks, ended);
+        // nothing decoded, the output gets a block of silence at once
+        let start = Instant::now();
+        assert_eq!(source.current_frame_len(), Some(20));
+        assert!(source.by_ref().take(20).all(|sample| sample == 0));
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        send(&tx, CHUNK);
+        let wait = Instant::now();
+        while source.current_frame_len() == Some(20) && wait.elapsed() < Duration::from_secs(5) {
+            source.by_ref().take(20).count();
+        }
+        assert_eq!(source.current_frame_len(), Some(CHUNK));
+        assert!(source.by_ref().take(CHUNK).all(|sample| sample == 1));
+
+        // the end of source ends it after the decoded samples
+        send(&tx, 2);
+        drop(tx);
+        let samples: Vec<i16> = source.collect();
+        assert_eq!(samples.iter().filter(|sample| **sample == 1).count(), 2);
+    }
+}
@@ -0,0 +1,224 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = "index.json";
+const TEMP_PREFIX: &str = ".tmp";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry {
+    size: u64,
+    // value of the use counter when last played, smaller is older
+    last_used: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Index {
+    counter: u64,
+    entries: HashMap<String, Entry>,
+}
+
+// downloaded songs on disk keyed by song id and bitrate
+// least recently used songs are removed when size is over max_size
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    index: Index,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, max_size: u64) -> Cache {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("create cache dir failed {}", e);
+        }
+        let index = fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_str::<Index>(&data).ok())
+            .unwrap_or_default();
+        let mut cache = Cache {
+            dir,
+            max_size,
+            index,
+        };
+        cache.clean();
+        cache
+    }
+
+    fn key(id: i64, br: u32) -> String {
+        format!("{}_{}", id, br)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    pub fn contains(&self, id: i64, br: u32) -> bool {
+        let key = Cache::key(id, br);
+        self.index.entries.contains_key(&key) && self.path(&key).exists()
+    }
+
+    // new empty file in cache dir for download
+    // it is removed by player after playing
+    pub fn temp_file(&self) -> io::Result<(fs::File, PathBuf)> {
+        let (file, path) = tempfile::Builder::new()
+            .prefix(TEMP_PREFIX)
+            .tempfile_in(&self.dir)
+            .and_then(|temp| temp.keep().map_err(|e| e.error))?;
+        Ok((file, path))
+    }
+
+    // link cached song to a temp file, so eviction doesn't break the playing one
+    pub fn open(&mut self, id: i64, br: u32) -> Option<PathBuf> {
+        let key = Cache::key(id, br);
+        if !self.contains(id, br) {
+            return None;
+        }
+        match self.link_temp(&self.path(&key)) {
+            Ok(path) => {
+                self.touch(&key);
+                self.save();
+                Some(path)
+            }
+            Err(e) => {
+                error!("open cached song failed {}", e);
+                None
+            }
+        }
+    }
+
+    // temp file of a song outside cache, player removes it as other temp files
+    pub fn link_temp(&self, file: &Path) -> io::Result<PathBuf> {
+        let (_, path) = self.temp_file()?;
+        fs::remove_file(&path)?;
+        Cache::link(file, &path)?;
+        Ok(path)
+    }
+
+    // add finished download to cache and evict old songs
+    pub fn insert(&mut self, id: i64, br: u32, file: &Path) {
+        let size = match fs::metadata(file) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return,
+        };
+        if size == 0 || size > self.max_size {
+            return;
+        }
+        let key = Cache::key(id, br);
+        let path = self.path(&key);
+        fs::remove_file(&path).ok();
+        if let Err(e) = Cache::link(file, &path) {
+            error!("cache song failed {}", e);
+            return;
+        }
+        self.index
+            .entries
+            .insert(key.to_owned(), Entry { size, last_used: 0 });
+        self.touch(&key);
+        self.evict();
+        self.save();
+    }
+
+    fn link(from: &Path, to: &Path) -> io::Result<()> {
+        // hard link is free, copy when the file system doesn't support it
+        fs::hard_link(from, to).or_else(|_| fs::copy(from, to).map(|_| ()))
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.index.counter += 1;
+        if let Some(entry) = self.index.entries.get_mut(key) {
+            entry.last_used = self.index.counter;
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.index.entries.values().map(|entry| entry.size).sum()
+    }
+
+    // remove least recently used songs until size is under max_size
+    fn evict(&mut self) {
+        while self.size() > self.max_size {
+            let oldest = self
+                .index
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.to_owned());
+            match oldest {
+                Some(key) => {
+                    fs::remove_file(self.path(&key)).ok();
+                    self.index.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    // drop entries whose file is gone and temp files left by a crash
+    fn clean(&mut self) {
+        let dir = self.dir.to_owned();
+        self.index.entries.retain(|key, _| dir.join(key).exists());
+        if let Ok(files) = fs::read_dir(&self.dir) {
+            for file in files.filter_map(|file| file.ok()) {
+                if file.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+                    fs::remove_file(file.path()).ok();
+                }
+            }
+        }
+        self.evict();
+        self.save();
+    }
+
+    fn save(&self) {
+        match serde_json::to_string(&self.index) {
+            Ok(data) => {
+                if let Err(e) = fs::write(self.dir.join(INDEX_FILE), data) {
+                    error!("save cache index failed {}", e);
+                }
+            }
+            Err(e) => error!("serialize cache index failed {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(dir: &Path, size: usize) -> PathBuf {
+        let path = dir.join(format!("song{}", size));
+        fs::write(&path, vec![0u8; size]).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = tempfile::tempdir().unwrap();
+        let mut cache = Cache::new(dir.path().to_path_buf(), 25);
+        cache.insert(1, 320000, &song(files.path(), 10));
+        cache.insert(2, 320000, &song(files.path(), 11));
+        assert!(cache.open(1, 320000).is_some());
+        cache.insert(3, 320000, &song(files.path(), 12));
+        assert!(cache.contains(1, 320000));
+        assert!(!cache.contains(2, 320000));
+        assert!(cache.contains(3, 320000));
+        assert!(!cache.contains(3, 128000));
+
+        // index is kept on disk
+        let cache = Cache::new(dir.path().to_path_buf(), 25);
+        assert!(cache.contains(1, 320000));
+        assert!(!cache.contains(2, 320000));
+    }
+
+    #[test]
+    fn test_skip_song_over_max_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = tempfile::tempdir().unwrap();
+        let mut cache = Cache::new(dir.path().to_path_buf(), 5);
+        cache.insert(1, 320000, &song(files.path(), 10));
+        assert!(!cache.contains(1, 320000));
+        assert!(cache.open(1, 320000).is_none());
+    }
+}
@@ -0,0 +1,489 @@
+use super::mp3;
+use super::stream::StreamFile;
+use failure::err_msg;
+use rodio::Source;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+// audio formats the player decodes
+#[derive(Copy, Clone, Eq, PartialEq, Debug, PartialOrd, Ord)]
+pub enum Format {
+    Mp3,
+    Flac,
+    // vorbis in ogg
+    Ogg,
+    // aac in m4a
+    Mp4,
+    // raw aac stream
+    Aac,
+}
+
+impl Format {
+    pub fn from_extension(ext: &str) -> Option<Format> {
+        match ext.to_lowercase().as_str() {
+            "mp3" => Some(Format::Mp3),
+            "flac" => Some(Format::Flac),
+            "ogg" | "oga" => Some(Format::Ogg),
+            "m4a" | "mp4" => Some(Format::Mp4),
+            "aac" => Some(Format::Aac),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Flac => "flac",
+            Format::Ogg => "ogg",
+            Format::Mp4 => "m4a",
+            Format::Aac => "aac",
+        }
+    }
+}
+
+// format by the magic bytes at file head, id3 tag is skipped before
+fn sniff(head: &[u8]) -> Option<Format> {
+    if head.starts_with(b"fLaC") {
+        Some(Format::Flac)
+    } else if head.starts_with(b"OggS") {
+        Some(Format::Ogg)
+    } else if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        Some(Format::Mp4)
+    } else if head.len() >= 2 && head[0] == 0xff && head[1] & 0xe0 == 0xe0 {
+        // mpeg frame sync, layer bits of adts aac are zero
+        if head[1] & 0x06 == 0 {
+            Some(Format::Aac)
+        } else {
+            Some(Format::Mp3)
+        }
+    } else {
+        None
+    }
+}
+
+// size of id3v2 tag at file head
+fn tag_size(head: &[u8]) -> Option<u64> {
+    if head.len() < 10 || !head.starts_with(b"ID3") {
+        return None;
+    }
+    let size = head[6..10]
+        .iter()
+        .fold(0, |size, b| size << 7 | u64::from(b & 0x7f));
+    // footer flag
+    let footer = if head[5] & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + size + footer)
+}
+
+fn read_head<R: Read>(file: &mut R) -> io::Result<Vec<u8>> {
+    let mut head = vec![];
+    file.take(12).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+// find format of file, none if unknown or the head is not downloaded yet
+pub fn detect(file: &Path) -> io::Result<Option<Format>> {
+    let mut f = File::open(file)?;
+    let head = read_head(&mut f)?;
+    match tag_size(&head) {
+        Some(size) => {
+            // netease flac may have id3 tag as mp3
+            f.seek(SeekFrom::Start(size))?;
+            let head = read_head(&mut f)?;
+            if head.len() < 4 {
+                return Ok(None);
+            }
+            Ok(sniff(&head).or(Some(Format::Mp3)))
+        }
+        None => Ok(sniff(&head)),
+    }
+}
+
+// duration from container headers
+pub fn duration(file: &Path, format: Format) -> Option<Duration> {
+    if format == Format::Mp3 {
+        // mp3 without xing header has no total frames, count them
+        return ::mp3_duration::from_path(file).ok();
+    }
+    let reader = probe(Box::new(File::open(file).ok()?), format).ok()?;
+    let track = reader.default_track()?;
+    let frames = track.codec_params.n_frames?;
+    let time_base = track.codec_params.time_base?;
+    let time = time_base.calc_time(frames);
+    Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+}
+
+// decoding a file in download waits for the bytes
+// source starts at start, symphonia formats seek in the container
+// seeking is done at the first read, so bytes not downloaded yet
+// hold the decode thread instead of the player commands
+pub fn open(
+    file: StreamFile,
+    format: Format,
+    start: Duration,
+) -> Result<Box<dyn Source<Item = i16> + Send>, failure::Error> {
+    match format {
+        Format::Mp3 if start.as_millis() > 0 => {
+            let mut file = file;
+            let head = read_head(&mut file)?;
+            let stream = mp3::Stream::find(&mut file, tag_size(&head).unwrap_or(0))?;
+            file.seek(SeekFrom::Start(0))?;
+            match stream {
+                Some(stream) => Ok(Box::new(Mp3From {
+                    file: Some(file),
+                    stream,
+                    start,
+                    decoder: None,
+                })),
+                None => skip(file, start),
+            }
+        }
+        Format::Mp3 => Ok(Box::new(rodio::Decoder::new(BufReader::new(file))?)),
+        _ => {
+            let mut source = SymphoniaSource::new(file, format)?;
+            if start.as_millis() > 0 {
+                source.start = Some(start);
+            }
+            Ok(Box::new(source))
+        }
+    }
+}
+
+// decode mp3 without frame headers from the file start, rodio can't seek a decoder
+// so the decoded samples before start are skipped
+fn skip(
+    file: StreamFile,
+    start: Duration,
+) -> Result<Box<dyn Source<Item = i16> + Send>, failure::Error> {
+    let source = rodio::Decoder::new(BufReader::new(file))?;
+    let samples = start.as_millis() as u64
+        * u64::from(source.sample_rate())
+        * u64::from(source.channels())
+        / 1000;
+    Ok(Box::new(Skip {
+        source,
+        samples: samples as usize,
+    }))
+}
+
+// mp3 decoder from the frame near start, which is found by the table of contents
+// in the first frame or the bitrate. it's done at the first read as the bytes
+// there may be not downloaded yet
+struct Mp3From {
+    file: Option<StreamFile>,
+    stream: mp3::Stream,
+    start: Duration,
+    decoder: Option<rodio::Decoder<BufReader<StreamFile>>>,
+}
+
+impl Mp3From {
+    fn seek(
+        &self,
+        mut file: StreamFile,
+    ) -> Result<rodio::Decoder<BufReader<StreamFile>>, failure::Error> {
+        let offset = self.stream.offset(self.start);
+        let (position, _) = mp3::sync(&mut file, offset, Some(&self.stream.header))?
+            .ok_or_else(|| err_msg("no mp3 frame after seek position"))?;
+        file.seek(SeekFrom::Start(position))?;
+        Ok(rodio::Decoder::new(BufReader::new(file))?)
+    }
+}
+
+impl Iterator for Mp3From {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(file) = self.file.take() {
+            match self.seek(file) {
+                Ok(decoder) => self.decoder = Some(decoder),
+                Err(e) => {
+                    error!("seek mp3 to {:?} failed {}", self.start, e);
+                    return None;
+                }
+            }
+        }
+        self.decoder.as_mut()?.next()
+    }
+}
+
+impl Source for Mp3From {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.decoder.as_ref().and_then(|decoder| decoder.current_frame_len())
+    }
+
+    // format of the first frame until the decoder starts
+    fn channels(&self) -> u16 {
+        match &self.decoder {
+            Some(decoder) => decoder.channels(),
+            None => self.stream.header.channels,
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match &self.decoder {
+            Some(decoder) => decoder.sample_rate(),
+            None => self.stream.header.sample_rate,
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// source skipping its first samples when it's read
+struct Skip<S> {
+    source: S,
+    samples: usize,
+}
+
+impl<S> Iterator for Skip<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.samples > 0 {
+            let samples = std::mem::replace(&mut self.samples, 0);
+            self.source.nth(samples - 1)?;
+        }
+        self.source.next()
+    }
+}
+
+impl<S> Source for Skip<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+fn probe(
+    source: Box<dyn MediaSource>,
+    format: Format,
+) -> Result<Box<dyn FormatReader>, failure::Error> {
+    let mut hint = Hint::new();
+    hint.with_extension(format.extension());
+    let stream = MediaSourceStream::new(source, Default::default());
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    Ok(probed.format)
+}
+
+// decoding starts this much before the seek position
+const SEEK_PREROLL: Duration = Duration::from_millis(200);
+
+// rodio source of formats rodio can't decode
+struct SymphoniaSource {
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    // position to seek at the first read
+    start: Option<Duration>,
+    // samples before it are dropped after a seek
+    seek_ts: u64,
+    buffer: Option<SampleBuffer<i16>>,
+    position: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl SymphoniaSource {
+    fn new(file: StreamFile, format: Format) -> Result<SymphoniaSource, failure::Error> {
+        let reader = probe(Box::new(file), format)?;
+        let track = reader
+            .default_track()
+            .ok_or_else(|| err_msg("no audio track in file"))?;
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+        let decoder =
+            symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+        let mut source = SymphoniaSource {
+            reader,
+            decoder,
+            track_id,
+            time_base,
+            start: None,
+            seek_ts: 0,
+            buffer: None,
+            position: 0,
+            channels: 0,
+            sample_rate: 0,
+        };
+        // channels and sample rate are known after the first packet
+        if !source.decode_next() {
+            return Err(err_msg("no audio data in file"));
+        }
+        Ok(source)
+    }
+
+    // move to the packet of start, channels and sample rate stay the same
+    fn seek(&mut self, start: Duration) -> Result<(), failure::Error> {
+        let time = Time::from(start.as_secs_f64());
+        // vorbis decodes nothing from the first packet after reset, so seek before start
+        let (time, seek_ts) = match self.time_base {
+            Some(time_base) => (
+                Time::from(start.checked_sub(SEEK_PREROLL).unwrap_or_default().as_secs_f64()),
+                Some(time_base.calc_timestamp(time)),
+            ),
+            None => (time, None),
+        };
+        let seeked = self.reader.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time,
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.buffer = None;
+        self.position = 0;
+        self.seek_ts = seek_ts.unwrap_or(seeked.required_ts);
+        Ok(())
+    }
+
+    // frames of a timestamp span
+    fn frames(time_base: Option<TimeBase>, ts: u64, sample_rate: u32) -> u64 {
+        match time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * f64::from(sample_rate)) as u64
+            }
+            None => ts,
+        }
+    }
+
+    // decode next packet into buffer, false at the end of stream
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let packet = match self.reader.next_packet() {
+                Ok(packet) => packet,
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    // accurate seek starts at a packet before the seek position
+                    let skip = if packet.ts() < self.seek_ts {
+                        SymphoniaSource::frames(self.time_base, self.seek_ts - packet.ts(), spec.rate)
+                    } else {
+                        0
+                    };
+                    if skip >= decoded.frames() as u64 {
+                        continue;
+                    }
+                    let capacity = decoded.capacity() * spec.channels.count();
+                    let buffer = match self.buffer.take() {
+                        Some(buffer) if buffer.capacity() >= capacity => buffer,
+                        _ => SampleBuffer::new(decoded.capacity() as u64, spec),
+                    };
+                    let buffer = self.buffer.get_or_insert(buffer);
+                    buffer.copy_interleaved_ref(decoded);
+                    self.position = skip as usize * spec.channels.count();
+                    self.channels = spec.channels.count() as u16;
+                    self.sample_rate = spec.rate;
+                    return true;
+                }
+                // skip broken packet
+                Err(DecodeError::DecodeError(e)) => debug!("decode packet failed {}", e),
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Iterator for SymphoniaSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(start) = self.start.take() {
+            if let Err(e) = self.seek(start) {
+                error!("seek to {:?} failed {}", start, e);
+                return None;
+            }
+        }
+        loop {
+            if let Some(buffer) = &self.buffer {
+                if let Some(sample) = buffer.samples().get(self.position) {
+                    self.position += 1;
+                    return Some(*sample);
+                }
+            }
+            if !self.decode_next() {
+                return None;
+            }
+        }
+    }
+}
+
+impl Source for SymphoniaSource {
+    // channels and sample rate don't change in a song
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_format() {
+        assert_eq!(sniff(b"fLaC\0\0\0\x22"), Some(Format::Flac));
+        assert_eq!(sniff(b"OggS\0\x02"), Some(Format::Ogg));
+        assert_eq!(sniff(b"\0\0\0\x20ftypM4A "), Some(Format::Mp4));
+        assert_eq!(sniff(&[0xff, 0xfb, 0x90, 0x64]), Some(Format::Mp3));
+        assert_eq!(sniff(&[0xff, 0xf1, 0x50, 0x80]), Some(Format::Aac));
+        assert_eq!(sniff(b"<html>"), None);
+        assert_eq!(
+            tag_size(&[b'I', b'D', b'3', 4, 0, 0, 0, 0, 0x02, 0x01]),
+            Some(10 + 257)
+        );
+        assert_eq!(tag_size(b"fLaC\0\0\0\x22\0\0"), None);
+    }
+}
@@ -1,48 +1,245 @@
 use std::io::prelude::*;
+use failure::err_msg;
 use futures::channel::oneshot::Sender;
-use reqwest::header::{CACHE_CONTROL, PRAGMA, HeaderMap, UPGRADE_INSECURE_REQUESTS, ACCEPT, ACCEPT_ENCODING, USER_AGENT};
-use reqwest::Method;
-use tempfile::NamedTempFile;
+use reqwest::header::{CACHE_CONTROL, PRAGMA, HeaderMap, UPGRADE_INSECURE_REQUESTS, ACCEPT, USER_AGENT, RANGE};
+use reqwest::{Method, StatusCode};
+use std::fmt;
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+use tokio::time::delay_for;
 
-#[tokio::main]
-pub async fn fetch_data(url: &str, buffer: NamedTempFile, tx: Sender<String>) -> Result<(), failure::Error> {
+// retries of a broken download, resumed from the downloaded bytes
+const MAX_RETRIES: u32 = 5;
+// delay before first retry, doubled for each next one
+const RETRY_DELAY: Duration = Duration::from_millis(500);
 
-    // debug!("start fetch_data");
-    let mut buffer = buffer;
+lazy_static! {
+    // all downloads share one runtime and client
+    static ref RUNTIME: Runtime = Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .thread_name("fetch")
+        .build()
+        .expect("build download runtime failed");
+    static ref CLIENT: reqwest::Client = client();
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    Cancelled,
+    Status(u16),
+}
+
+impl failure::Fail for FetchError {}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Cancelled => write!(f, "download cancelled"),
+            FetchError::Status(status) => write!(f, "download failed with status {}", status),
+        }
+    }
+}
+
+impl FetchError {
+    // server errors and broken connections may pass next time
+    fn is_retryable(error: &failure::Error) -> bool {
+        match error.downcast_ref::<FetchError>() {
+            Some(FetchError::Cancelled) => false,
+            Some(FetchError::Status(status)) => *status >= 500 || *status == 429,
+            None => true,
+        }
+    }
+}
+
+// download state shared with the thread of fetch_data
+#[derive(Debug, Default)]
+pub struct Progress {
+    downloaded: AtomicU64,
+    total: AtomicU64,
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl Progress {
+    // bytes written to file
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded.load(Ordering::Relaxed)
+    }
+
+    // content length, none if server doesn't tell it
+    pub fn total(&self) -> Option<u64> {
+        match self.total.load(Ordering::Relaxed) {
+            0 => None,
+            total => Some(total),
+        }
+    }
+
+    // paused download holds the connection until resumed or cancelled
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // stop download at next chunk
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
 
+    // no more bytes will be written, the download is done or failed
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    // count bytes after they are written to file
+    pub(super) fn add_downloaded(&self, bytes: u64) {
+        self.downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+fn client() -> reqwest::Client {
     let mut headers = HeaderMap::new();
     headers.insert(CACHE_CONTROL, "no-cache".parse().unwrap());
     headers.insert(PRAGMA, "no-cache".parse().unwrap());
     headers.insert(UPGRADE_INSECURE_REQUESTS, "1".parse().unwrap());
     // headers.insert(HOST, "m701.music.126.net".parse().unwrap());
     headers.insert(ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3".parse().unwrap());
-    headers.insert(ACCEPT_ENCODING, "gzip,deflate,br".parse().unwrap());
     headers.insert(
         USER_AGENT,
         "User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:65.0) Gecko/20100101 Firefox/65.0".parse().unwrap(),
     );
-    let client = reqwest::Client::builder()
+    reqwest::Client::builder()
+        .default_headers(headers)
+        // range of resumed download counts bytes of the file, not of gzip
+        .no_gzip()
         // no need proxy but can add it in config
         // .proxy(reqwest::Proxy::all("socks5://127.0.0.1:3333").expect("proxy error"))
-        .build().expect("builder error");
-    let builder = client.request(Method::GET, url).headers(headers);
-    let mut res = builder.send().await?;
+        .build().expect("builder error")
+}
 
-    // debug!("start download");
-    if let Some(chunk) = res.chunk().await? {
-        // debug!("first chunk");
-        buffer.write(&chunk[..]).unwrap();
-        send_msg(tx);
+// download url to buffer file, tx gets a message when the first chunk is written
+// broken download is resumed with range request
+pub fn fetch_data(
+    url: &str,
+    buffer: File,
+    tx: Sender<String>,
+    progress: Arc<Progress>,
+) -> Result<(), failure::Error> {
+    let task = RUNTIME.spawn(fetch(url.to_owned(), buffer, tx, progress.clone()));
+    let result = futures::executor::block_on(task);
+    progress.finish();
+    result?
+}
+
+async fn fetch(
+    url: String,
+    buffer: File,
+    tx: Sender<String>,
+    progress: Arc<Progress>,
+) -> Result<(), failure::Error> {
+    let mut buffer = buffer;
+    let mut first_chunk = Some(tx);
+    let mut retries = 0;
+    loop {
+        match transfer(&url, &mut buffer, &mut first_chunk, &progress).await {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                if retries >= MAX_RETRIES || !FetchError::is_retryable(&e) {
+                    return Err(e);
+                }
+                let delay = RETRY_DELAY * 2u32.pow(retries);
+                retries += 1;
+                warn!("download {} failed {}, retry {} in {:?}", url, e, retries, delay);
+                delay_for(delay).await;
+            }
+        }
+    }
+}
+
+// one request from the downloaded bytes to the end
+async fn transfer(
+    url: &str,
+    buffer: &mut File,
+    first_chunk: &mut Option<Sender<String>>,
+    progress: &Progress,
+) -> Result<(), failure::Error> {
+    let start = progress.downloaded();
+    let mut builder = CLIENT.request(Method::GET, url);
+    if start > 0 {
+        builder = builder.header(RANGE, format!("bytes={}-", start));
+    }
+    let mut res = builder.send().await?;
+    if !res.status().is_success() {
+        return Err(FetchError::Status(res.status().as_u16()).into());
+    }
+    // server without range support sends the whole file again
+    let mut skip = if res.status() == StatusCode::PARTIAL_CONTENT {
+        0
+    } else {
+        start
+    };
+    if let Some(length) = res.content_length() {
+        progress.total.store(start - skip + length, Ordering::Relaxed);
     }
 
+    // debug!("start download");
     while let Some(chunk) = res.chunk().await? {
         // bytes
-        buffer.write(&chunk[..]).unwrap();
+        let offset = skip.min(chunk.len() as u64) as usize;
+        skip -= offset as u64;
+        buffer.write_all(&chunk[offset..])?;
+        progress.add_downloaded((chunk.len() - offset) as u64);
+        if progress.downloaded() > 0 {
+            if let Some(tx) = first_chunk.take() {
+                // debug!("first chunk");
+                send_msg(tx);
+            }
+        }
+        while progress.is_paused() && !progress.is_cancelled() {
+            delay_for(Duration::from_millis(100)).await;
+        }
+        if progress.is_cancelled() {
+            return Err(FetchError::Cancelled.into());
+        }
+    }
+    // connection closed before the end
+    if progress.total().is_some_and(|total| progress.downloaded() < total) {
+        return Err(err_msg("download is incomplete"));
     }
     // debug!("finish downloa");
     Ok(())
 }
 
 fn send_msg(tx: Sender<String>) {
-    tx.send("ok".to_owned()).expect("send error");
+    // receiver is gone if player loaded another track
+    tx.send("ok".to_owned()).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retryable_errors() {
+        assert!(FetchError::is_retryable(&err_msg("connection reset")));
+        assert!(FetchError::is_retryable(&FetchError::Status(503).into()));
+        assert!(FetchError::is_retryable(&FetchError::Status(429).into()));
+        assert!(!FetchError::is_retryable(&FetchError::Status(404).into()));
+        assert!(!FetchError::is_retryable(&FetchError::Cancelled.into()));
+    }
 }
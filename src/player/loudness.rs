@@ -0,0 +1,206 @@
+use super::super::settings::Normalisation;
+use super::decoder;
+use super::stream::StreamFile;
+use failure::err_msg;
+use rodio::Source;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const LOUDNESS_FILE: &str = "loudness.json";
+// loudness of tracks after normalisation in dB of full scale
+const TARGET: f32 = -14.0;
+// rms window of analysis in ms
+const WINDOW: u32 = 50;
+
+// measured loudness of a song
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub album: Option<i64>,
+    // loud part of the song in dB of full scale
+    pub loudness: f32,
+    // max sample, 1.0 is full scale
+    pub peak: f32,
+}
+
+// loudness of played songs keyed by song id, kept in cache dir
+pub struct Loudness {
+    file: PathBuf,
+    entries: HashMap<i64, Entry>,
+    mode: Normalisation,
+    // dB
+    pre_amp: f32,
+}
+
+impl Loudness {
+    pub fn new(dir: &Path, mode: Normalisation, pre_amp: f32) -> Loudness {
+        let file = dir.join(LOUDNESS_FILE);
+        let entries = fs::read_to_string(&file)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        Loudness {
+            file,
+            entries,
+            mode,
+            pre_amp,
+        }
+    }
+
+    // analysis is only needed when normalisation is on
+    pub fn needs(&self, id: i64) -> bool {
+        self.mode != Normalisation::Off && !self.entries.contains_key(&id)
+    }
+
+    pub fn insert(&mut self, id: i64, entry: Entry) {
+        self.entries.insert(id, entry);
+        match serde_json::to_string(&self.entries) {
+            Ok(data) => {
+                if let Err(e) = fs::write(&self.file, data) {
+                    error!("save loudness failed {}", e);
+                }
+            }
+            Err(e) => error!("serialize loudness failed {}", e),
+        }
+    }
+
+    // volume factor of song, song not analysed yet gets the pre-amp only
+    pub fn gain(&self, id: Option<i64>, album: Option<i64>) -> f32 {
+        if self.mode == Normalisation::Off {
+            return 1.0;
+        }
+        let entry = id.and_then(|id| self.entries.get(&id)).copied();
+        let entry = match (self.mode, album) {
+            // the analysed songs of album stand for the whole album
+            (Normalisation::Album, Some(album)) => {
+                let songs = self
+                    .entries
+                    .values()
+                    .filter(|entry| entry.album == Some(album))
+                    .collect::<Vec<&Entry>>();
+                if songs.is_empty() {
+                    entry
+                } else {
+                    let power = songs
+                        .iter()
+                        .map(|entry| 10f32.powf(entry.loudness / 10.0))
+                        .sum::<f32>()
+                        / songs.len() as f32;
+                    Some(Entry {
+                        album: Some(album),
+                        loudness: 10.0 * power.log10(),
+                        peak: songs.iter().map(|entry| entry.peak).fold(0.0, f32::max),
+                    })
+                }
+            }
+            _ => entry,
+        };
+        match entry {
+            Some(entry) => {
+                let gain = db_to_gain(TARGET - entry.loudness + self.pre_amp);
+                // don't clip the peak
+                if entry.peak > 0.0 {
+                    gain.min(1.0 / entry.peak)
+                } else {
+                    gain
+                }
+            }
+            None => db_to_gain(self.pre_amp),
+        }
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// decode the whole file and take the 95th percentile of window rms as loudness
+pub fn analyse(file: &Path, album: Option<i64>) -> Result<Entry, failure::Error> {
+    let format = decoder::detect(file)?.ok_or_else(|| err_msg("unknown audio format"))?;
+    let source = decoder::open(StreamFile::open(file, None)?, format, Duration::from_millis(0))?;
+    let window = (source.sample_rate() * u32::from(source.channels()) * WINDOW / 1000) as usize;
+    let (windows, peak) = measure(source, window.max(1));
+    Ok(Entry {
+        album,
+        loudness: percentile(windows),
+        peak,
+    })
+}
+
+// mean square of sample windows and the peak
+fn measure<I: Iterator<Item = i16>>(samples: I, window: usize) -> (Vec<f32>, f32) {
+    let mut windows = vec![];
+    let mut peak = 0f32;
+    let mut sum = 0f64;
+    let mut count = 0;
+    for sample in samples {
+        let value = f32::from(sample) / 32768.0;
+        peak = peak.max(value.abs());
+        sum += f64::from(value * value);
+        count += 1;
+        if count == window {
+            windows.push((sum / count as f64) as f32);
+            sum = 0.0;
+            count = 0;
+        }
+    }
+    (windows, peak)
+}
+
+// dB of the 95th percentile window, silent song is quiet enough
+fn percentile(mut windows: Vec<f32>) -> f32 {
+    windows.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    match windows.get(windows.len() * 95 / 100) {
+        Some(square) if *square > 0.0 => 10.0 * square.log10(),
+        _ => TARGET,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_loudness() {
+        // square wave of half scale
+        let samples = (0..4800).map(|i| if i % 2 == 0 { 16384 } else { -16384 });
+        let (windows, peak) = measure(samples, 480);
+        assert_eq!(windows.len(), 10);
+        assert_eq!(peak, 0.5);
+        assert!((percentile(windows) - -6.02).abs() < 0.01);
+        assert_eq!(percentile(vec![]), TARGET);
+    }
+
+    #[test]
+    fn test_gain_of_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut loudness = Loudness::new(dir.path(), Normalisation::Track, 0.0);
+        loudness.insert(
+            1,
+            Entry {
+                album: Some(1),
+                loudness: -8.0,
+                peak: 0.9,
+            },
+        );
+        loudness.insert(
+            2,
+            Entry {
+                album: Some(1),
+                loudness: -20.0,
+                peak: 0.8,
+            },
+        );
+        assert!((loudness.gain(Some(1), Some(1)) - db_to_gain(-6.0)).abs() < 0.001);
+        // quiet track is not raised over its peak
+        assert_eq!(loudness.gain(Some(2), Some(1)), 1.25);
+        assert_eq!(loudness.gain(Some(3), None), 1.0);
+
+        let loudness = Loudness::new(dir.path(), Normalisation::Album, 0.0);
+        assert_eq!(loudness.gain(Some(1), Some(1)), loudness.gain(Some(2), Some(1)));
+        let loudness = Loudness::new(dir.path(), Normalisation::Off, 6.0);
+        assert_eq!(loudness.gain(Some(1), Some(1)), 1.0);
+    }
+}
@@ -3,15 +3,37 @@ extern crate tokio;
 extern crate tempfile;
 
 mod player;
+mod ahead;
 mod fetch;
-// mod sink;
+mod cache;
+mod decoder;
+mod loudness;
+mod mp3;
+mod sink;
+mod stream;
+mod tempo;
 // mod range_set;
 mod track;
 // mod fetch_data;
 
+use super::settings::Settings;
+use super::sleep::Sleep;
+use cache::Cache;
+use decoder::Format;
+use loudness::Loudness;
+pub use fetch::{fetch_data, Progress};
 use player::Player;
-// use sink::find;
-use std::sync::mpsc::Sender;
+use tempo::{Speeds, MAX_SPEED, MIN_SPEED, SPEED_STEP};
+use track::Clock;
+use sink::find;
+pub use sink::devices;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 #[allow(unused)]
 pub enum PlayerCommand {
@@ -19,14 +41,86 @@ pub enum PlayerCommand {
     Pause,
     Stop,
     PlayPause,
-    Seek(i32),
+    // offset from current position in ms
+    Seek(i64),
     Next,
     Previous,
-    Load(String),
-    Position(i32, u64),
+    Load(Media, Detail),
+    // media, if crossfade into it and song detail
+    Preload(Media, bool, Detail),
+    // drop preloaded track, nothing is played after current one
+    ClearPreload,
+    // id of track and absolute position in ms
+    Position(i64, u64),
+    Volume(f32),
+    // name of output device
+    Device(String),
+    // playback speed of dj program
+    Speed(f32),
+    // sleep timer, none cancels it
+    Sleep(Option<Sleep>),
     Metadata(MetaInfo, Sender<String>),
 }
 
+// what player loads
+#[allow(unused)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Media {
+    // plain url, not cached
+    Url(String),
+    // downloaded song
+    File(PathBuf),
+    // song id and bitrate are the key of cache
+    // url is none when the song is cached
+    Song {
+        id: i64,
+        br: u32,
+        url: Option<String>,
+    },
+}
+
+impl Media {
+    // songs are the same by id and bitrate, the url of song expires
+    pub fn is_same(&self, other: &Media) -> bool {
+        match (self, other) {
+            (Media::Song { id, br, .. }, Media::Song { id: other_id, br: other_br, .. }) => {
+                id == other_id && br == other_br
+            }
+            _ => self == other,
+        }
+    }
+
+    // format by extension of url or file
+    fn format(&self) -> Option<Format> {
+        let path = match self {
+            Media::Url(url) | Media::Song { url: Some(url), .. } => url.split('?').next()?,
+            Media::File(file) => file.to_str()?,
+            Media::Song { url: None, .. } => return None,
+        };
+        Format::from_extension(Path::new(path).extension()?.to_str()?)
+    }
+}
+
+impl fmt::Display for Media {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Media::Url(url) => write!(f, "{}", url),
+            Media::File(file) => write!(f, "{}", file.display()),
+            Media::Song { id, br, .. } => write!(f, "song {} br {}", id, br),
+        }
+    }
+}
+
+// song detail from api for what the file doesn't tell
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Detail {
+    pub id: Option<i64>,
+    pub album: Option<i64>,
+    // dj radio of program, its speed is kept
+    pub radio: Option<i64>,
+    pub duration: Option<Duration>,
+}
+
 #[allow(unused)]
 pub enum MetaInfo {
     Volume,
@@ -37,103 +131,293 @@ pub enum MetaInfo {
     Info,
 }
 
+// state events send from player thread
+// loaded track comes with its download, none when its file is complete,
+// and the clock of its position
+pub enum PlayerEvent {
+    Buffering,
+    Loaded(Duration, Option<Arc<Progress>>, Arc<Clock>),
+    Playing,
+    Paused,
+    Stopped,
+    EndOfTrack,
+    // preloaded track starts playing after current one without gap
+    TrackChanged(Duration, Option<Arc<Progress>>, Arc<Clock>),
+    DeviceChanged(String),
+    Error(String),
+}
+
+// handle of player thread
+// keep the player state from events for ui
 pub struct Nplayer {
-    pub player: player::Player,
+    commands: Sender<PlayerCommand>,
+    events: Receiver<PlayerEvent>,
+    // position of current track
+    clock: Option<Arc<Clock>>,
+    duration: Option<Duration>,
+    // download of current track
+    download: Option<Arc<Progress>>,
+    playing: bool,
+    loading: bool,
+    volume: f32,
+    // volume factor of sleep timer fade out
+    fade: f32,
+    device: Option<String>,
+    cache: Arc<Mutex<Cache>>,
 }
 
 impl Nplayer {
-    pub fn new() -> Nplayer {
-        // let backend = find(None).unwrap();
-        let mplayer = Player::new();
-        debug!("init player");
+    pub fn new(settings: &Settings) -> Nplayer {
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+        let crossfade = Duration::from_secs(settings.crossfade);
+        let cache = Arc::new(Mutex::new(Cache::new(
+            settings.cache_dir.to_owned(),
+            settings.cache_size * 1024 * 1024,
+        )));
+        let player_cache = cache.clone();
+        let loudness = Arc::new(Mutex::new(Loudness::new(
+            &settings.cache_dir,
+            settings.normalisation,
+            settings.pre_amp,
+        )));
+        let backend_name = settings.backend.to_owned();
+        let device = settings.device.to_owned();
+        let cache_dir = settings.cache_dir.to_owned();
+        thread::spawn(move || {
+            let backend = match find(&backend_name, device.as_deref()) {
+                Ok(backend) => backend,
+                Err(e) => {
+                    error!("open audio backend {} failed {}", backend_name, e);
+                    event_tx
+                        .send(PlayerEvent::Error(format!("no audio output, {}", e)))
+                        .ok();
+                    sink::null()
+                }
+            };
+            let speeds = Speeds::new(&cache_dir);
+            let mut mplayer = Player::new(
+                backend,
+                event_tx,
+                crossfade,
+                player_cache,
+                loudness,
+                speeds,
+            );
+            debug!("init player");
+            mplayer.run(command_rx);
+        });
         Nplayer {
-            player: mplayer,
+            commands,
+            events,
+            clock: None,
+            duration: None,
+            download: None,
+            playing: false,
+            loading: false,
+            volume: 1.0,
+            fade: 1.0,
+            device: settings.device.to_owned(),
+            cache,
+        }
+    }
+
+    fn command(&self, cmd: PlayerCommand) {
+        if let Err(e) = self.commands.send(cmd) {
+            error!("send player command failed {}", e);
         }
     }
 
-    pub fn play_url(&mut self, url: &str) {
-        self.player.load(url.to_owned(), true);
+    // receive events from player thread and update state
+    pub fn poll_events(&mut self) -> Vec<PlayerEvent> {
+        let events = self.events.try_iter().collect::<Vec<PlayerEvent>>();
+        for event in events.iter() {
+            match event {
+                PlayerEvent::Buffering => {
+                    self.clock = None;
+                    self.duration = None;
+                    self.download = None;
+                    self.playing = false;
+                    self.loading = true;
+                }
+                PlayerEvent::Loaded(duration, download, clock) => {
+                    self.clock = Some(clock.clone());
+                    self.duration = Some(*duration);
+                    self.download = download.clone();
+                    self.loading = false;
+                }
+                PlayerEvent::TrackChanged(duration, download, clock) => {
+                    self.clock = Some(clock.clone());
+                    self.duration = Some(*duration);
+                    self.download = download.clone();
+                    self.playing = true;
+                    self.loading = false;
+                }
+                PlayerEvent::Playing => {
+                    self.playing = true;
+                }
+                PlayerEvent::Paused | PlayerEvent::Stopped | PlayerEvent::EndOfTrack => {
+                    self.playing = false;
+                }
+                PlayerEvent::DeviceChanged(name) => {
+                    self.device = Some(name.to_owned());
+                }
+                PlayerEvent::Error(_) => {
+                    self.playing = false;
+                    self.loading = false;
+                }
+            }
+        }
+        events
+    }
+
+    pub fn load(&mut self, media: Media, detail: Detail) {
+        self.command(PlayerCommand::Load(media, detail));
+    }
+
+    // download next track for gapless playback or crossfade
+    pub fn preload(&mut self, media: Media, crossfade: bool, detail: Detail) {
+        self.command(PlayerCommand::Preload(media, crossfade, detail));
+    }
+
+    pub fn clear_preload(&mut self) {
+        self.command(PlayerCommand::ClearPreload);
+    }
+
+    // cached song can be loaded without url
+    pub fn is_cached(&self, id: i64, br: u32) -> bool {
+        match self.cache.lock() {
+            Ok(cache) => cache.contains(id, br),
+            Err(_) => false,
+        }
+    }
+
+    // play to another output device, position and volume are kept
+    pub fn set_device(&mut self, name: String) {
+        self.command(PlayerCommand::Device(name));
+    }
+
+    // output device selected, none is default device
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
     }
 
     pub fn is_playing(&mut self) -> bool {
-        self.player.status()
+        self.playing
+    }
+
+    // waiting for the first chunk of track
+    pub fn is_loading(&self) -> bool {
+        self.loading
     }
 
     pub fn pause(&mut self) {
-        self.player.pause()
+        self.command(PlayerCommand::Pause)
     }
 
     pub fn play(&mut self) {
-        self.player.play()
+        self.command(PlayerCommand::Play)
     }
 
-    #[allow(unused)]
     pub fn stop(&self) {
-        self.player.stop()
+        self.command(PlayerCommand::Stop)
     }
 
+    // position of samples played, shared by ui, lyrics and mpris
     pub fn get_position(&self) -> Option<u64> {
-        match self.player.current.clone() {
-            Some(current) => {
-                Some(current.elapsed().as_millis() as u64)
+        // duration from song detail may be shorter than the file
+        self.clock.as_ref().map(|clock| {
+            let position = clock.position().as_millis() as u64;
+            match self.get_duration() {
+                Some(duration) => position.min(duration),
+                None => position,
             }
-            None => { None }
-        }
+        })
     }
 
     pub fn get_duration(&self) -> Option<u64> {
-        match self.player.current.clone() {
-            Some(current) => {
-                Some(current.duration.as_millis() as u64)
-            }
-            None => { None }
+        self.duration.map(|duration| duration.as_millis() as u64)
+    }
+
+    // percent of current track downloaded, none when it's complete or size is unknown
+    pub fn get_buffered(&self) -> Option<u64> {
+        let download = self.download.as_ref()?;
+        if download.is_finished() {
+            return None;
         }
+        download
+            .total()
+            .map(|total| download.downloaded() * 100 / total)
     }
 
     pub fn seek_forwards(&mut self) {
-        // let next_duration = self.get_position().unwrap() + 3000;
-        // self.player.seek(ClockTime::from_mseconds(next_duration))
+        self.command(PlayerCommand::Seek(3000))
     }
 
     pub fn seek_backwards(&mut self) {
-        // let song_progress_ms = self.get_position().unwrap();
-        // let next_duration = if song_progress_ms < 3000 {
-            // 0
-        // } else {
-            // song_progress_ms - 3000
-        // };
-        // self.player.seek(ClockTime::from_mseconds(next_duration))
+        self.command(PlayerCommand::Seek(-3000))
     }
 
+    // seek offset ms from current position
     #[allow(unused)]
-    pub fn seek(&mut self, offset: i32) {
-        let next_duration = self.get_position().unwrap() as i32 + (offset * 1000);
-        // self.player
-            // .seek(ClockTime::from_mseconds(next_duration as u64))
+    pub fn seek(&mut self, offset: i64) {
+        self.command(PlayerCommand::Seek(offset))
     }
 
+    // seek to absolute position in ms
     #[allow(unused)]
     pub fn position(&mut self, position: u64) {
-        // self.player.seek(ClockTime::from_mseconds(position))
+        // track id is not used by player
+        self.command(PlayerCommand::Position(0, position))
+    }
+
+    // playback speed of current track, time of track is not scaled by it
+    pub fn get_speed(&self) -> f32 {
+        self.clock.as_ref().map_or(1.0, |clock| clock.speed())
+    }
+
+    pub fn increase_speed(&mut self) {
+        self.set_speed(self.get_speed() + SPEED_STEP);
+    }
+
+    pub fn decrease_speed(&mut self) {
+        self.set_speed(self.get_speed() - SPEED_STEP);
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.command(PlayerCommand::Speed(speed.clamp(MIN_SPEED, MAX_SPEED)));
     }
 
     pub fn increase_volume(&mut self) {
-        let current = self.player.get_volume();
+        let current = self.volume;
         let volume = if current < 9.9 {
             current + 0.1_f32
         } else {
             10.0_f32
         };
-        self.player.set_volume(volume);
+        self.set_volume(volume);
     }
 
     pub fn decrease_volume(&mut self) {
-        let current = self.player.get_volume();
+        let current = self.volume;
         let volume = if current > 0.1 {
             current - 0.1_f32
         } else {
             0.0_f32
         };
-        self.player.set_volume(volume);
+        self.set_volume(volume);
+    }
+
+    // lower volume for fade out of sleep timer, volume setting is kept
+    pub fn fade(&mut self, factor: f32) {
+        if factor != self.fade {
+            self.fade = factor;
+            self.command(PlayerCommand::Volume(self.volume * factor));
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.command(PlayerCommand::Volume(volume * self.fade));
     }
 }
@@ -0,0 +1,294 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+// bytes searched for a frame header after a seek offset
+const SYNC_WINDOW: u64 = 16 * 1024;
+
+// kbps of bitrate index, mpeg 1 layer 1, 2, 3 and mpeg 2 layer 1, 2 and 3
+const BITRATES: [[u16; 15]; 4] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+const MPEG2_LAYER1: [u16; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+
+// header of an mpeg audio frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Header {
+    mpeg1: bool,
+    layer: u8,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // bits per second
+    bitrate: u32,
+    padding: u32,
+}
+
+impl Header {
+    // none if bytes are not a frame header, free format is not supported
+    pub fn parse(bytes: &[u8]) -> Option<Header> {
+        if bytes.len() < 4 || bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let version = (bytes[1] >> 3) & 3;
+        let layer = match (bytes[1] >> 1) & 3 {
+            3 => 1,
+            2 => 2,
+            1 => 3,
+            _ => return None,
+        };
+        let bitrate = usize::from(bytes[2] >> 4);
+        let rate = usize::from((bytes[2] >> 2) & 3);
+        if version == 1 || bitrate == 0 || bitrate == 15 || rate == 3 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        let kbps = match (mpeg1, layer) {
+            (true, layer) => BITRATES[usize::from(layer) - 1][bitrate],
+            (false, 1) => MPEG2_LAYER1[bitrate],
+            (false, _) => BITRATES[3][bitrate],
+        };
+        // mpeg 2 has half of the rates and mpeg 2.5 a quarter
+        let shift = match version {
+            3 => 0,
+            2 => 1,
+            _ => 2,
+        };
+        Some(Header {
+            mpeg1,
+            layer,
+            sample_rate: [44100, 48000, 32000][rate] >> shift,
+            channels: if bytes[3] >> 6 == 3 { 1 } else { 2 },
+            bitrate: u32::from(kbps) * 1000,
+            padding: u32::from((bytes[2] >> 1) & 1),
+        })
+    }
+
+    // samples of a channel in a frame
+    fn samples(&self) -> u32 {
+        match self.layer {
+            1 => 384,
+            2 => 1152,
+            _ if self.mpeg1 => 1152,
+            _ => 576,
+        }
+    }
+
+    // bytes of frame with its header
+    pub fn size(&self) -> usize {
+        let len = if self.layer == 1 {
+            (12 * self.bitrate / self.sample_rate + self.padding) * 4
+        } else {
+            self.samples() / 8 * self.bitrate / self.sample_rate + self.padding
+        };
+        len as usize
+    }
+
+    // frames of one stream keep version, layer and sample rate
+    fn same_stream(&self, other: &Header) -> bool {
+        self.mpeg1 == other.mpeg1
+            && self.layer == other.layer
+            && self.sample_rate == other.sample_rate
+    }
+
+    // xing header is after the side info of layer 3 frame
+    fn side_info(&self) -> usize {
+        match (self.mpeg1, self.channels) {
+            (true, 1) => 17,
+            (true, _) => 32,
+            (false, 1) => 9,
+            (false, _) => 17,
+        }
+    }
+}
+
+fn be32(bytes: &[u8]) -> Option<u32> {
+    let bytes = bytes.get(..4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn be16(bytes: &[u8]) -> Option<u16> {
+    let bytes = bytes.get(..2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+// position of the first frame header at or after offset which is followed by
+// another header, like one is a header of the same stream
+pub fn sync<R: Read + Seek>(
+    file: &mut R,
+    offset: u64,
+    like: Option<&Header>,
+) -> io::Result<Option<(u64, Header)>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = vec![];
+    file.take(SYNC_WINDOW).read_to_end(&mut bytes)?;
+    for i in 0..bytes.len() {
+        let header = match (Header::parse(&bytes[i..]), like) {
+            (Some(header), Some(like)) if !header.same_stream(like) => continue,
+            (Some(header), _) => header,
+            (None, _) => continue,
+        };
+        let next = i + header.size();
+        let followed = match bytes.get(next..).and_then(Header::parse) {
+            Some(next) => next.same_stream(&header),
+            // last frame of file
+            None => next == bytes.len() && (bytes.len() as u64) < SYNC_WINDOW,
+        };
+        if followed {
+            return Ok(Some((offset + i as u64, header)));
+        }
+    }
+    Ok(None)
+}
+
+// first frame of the audio data, xing or vbri header in it tells where a time is
+pub struct Stream {
+    // position of first frame in file
+    start: u64,
+    pub header: Header,
+    frame: Vec<u8>,
+}
+
+impl Stream {
+    // first frame after the id3 tag at start
+    pub fn find<R: Read + Seek>(file: &mut R, start: u64) -> io::Result<Option<Stream>> {
+        let (start, header) = match sync(file, start, None)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        file.seek(SeekFrom::Start(start))?;
+        let mut frame = vec![];
+        file.take(header.size() as u64).read_to_end(&mut frame)?;
+        Ok(Some(Stream {
+            start,
+            header,
+            frame,
+        }))
+    }
+
+    // position in file near time, vbr files have a table of contents
+    // in the first frame, cbr files are at the bitrate
+    pub fn offset(&self, time: Duration) -> u64 {
+        let xing = 4 + self.header.side_info();
+        let offset = self
+            .frame
+            .get(xing..)
+            .and_then(|info| self.xing(info, time))
+            .or_else(|| self.frame.get(36..).and_then(|info| self.vbri(info, time)))
+            .unwrap_or_else(|| (time.as_secs_f64() * f64::from(self.header.bitrate) / 8.0) as u64);
+        self.start + offset
+    }
+
+    // seconds of frames
+    fn duration(&self, frames: u32) -> f64 {
+        f64::from(frames) * f64::from(self.header.samples()) / f64::from(self.header.sample_rate)
+    }
+
+    // toc has the 256th of bytes at each percent of time
+    fn xing(&self, info: &[u8], time: Duration) -> Option<u64> {
+        if !info.starts_with(b"Xing") && !info.starts_with(b"Info") {
+            return None;
+        }
+        let flags = be32(info.get(4..)?)?;
+        // frames and bytes are needed to use the toc
+        if flags & 3 != 3 {
+            return None;
+        }
+        let duration = self.duration(be32(info.get(8..)?)?);
+        let bytes = f64::from(be32(info.get(12..)?)?);
+        if duration <= 0.0 {
+            return None;
+        }
+        let percent = (time.as_secs_f64() / duration * 100.0).min(100.0);
+        let toc = if flags & 4 != 0 {
+            info.get(16..116)
+        } else {
+            None
+        };
+        let position = match toc {
+            Some(toc) => {
+                let index = (percent as usize).min(99);
+                let before = f64::from(toc[index]);
+                let after = toc.get(index + 1).map_or(256.0, |after| f64::from(*after));
+                (before + (after - before) * (percent - index as f64)) / 256.0
+            }
+            None => percent / 100.0,
+        };
+        Some((position * bytes) as u64)
+    }
+
+    // toc has the bytes of every some frames
+    fn vbri(&self, info: &[u8], time: Duration) -> Option<u64> {
+        if !info.starts_with(b"VBRI") {
+            return None;
+        }
+        let bytes = u64::from(be32(info.get(10..)?)?);
+        let frames = be32(info.get(14..)?)?;
+        let entries = usize::from(be16(info.get(18..)?)?);
+        let scale = u64::from(be16(info.get(20..)?)?);
+        let size = usize::from(be16(info.get(22..)?)?);
+        let per_entry = f64::from(be16(info.get(24..)?)?);
+        let duration = self.duration(frames);
+        if duration <= 0.0 {
+            return None;
+        }
+        if entries == 0 || per_entry == 0.0 || size == 0 || size > 4 {
+            return Some((time.as_secs_f64() / duration * bytes as f64) as u64);
+        }
+        let frame = time.as_secs_f64() / duration * f64::from(frames);
+        let whole = ((frame / per_entry) as usize).min(entries);
+        let toc = info.get(26..26 + entries * size)?;
+        let offset = toc
+            .chunks(size)
+            .take(whole)
+            .map(|entry| entry.iter().fold(0, |sum, b| sum << 8 | u64::from(*b)) * scale)
+            .sum::<u64>();
+        Some(offset.min(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // mpeg 1 layer 3, 128 kbps, 44100 Hz, stereo
+    const HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x64];
+
+    fn frames(count: usize) -> Vec<u8> {
+        let mut frame = HEADER.to_vec();
+        frame.resize(417, 0);
+        frame.repeat(count)
+    }
+
+    #[test]
+    fn test_sync_and_offset() {
+        let header = Header::parse(&HEADER).unwrap();
+        assert_eq!((header.sample_rate, header.channels, header.size()), (44100, 2, 417));
+
+        let mut file = b"junk\xff\xfb".to_vec();
+        file.extend(frames(3));
+        let mut file = Cursor::new(file);
+        let stream = Stream::find(&mut file, 0).unwrap().unwrap();
+        assert_eq!(stream.start, 6);
+        // cbr is at the bitrate
+        assert_eq!(stream.offset(Duration::from_secs(1)), 6 + 16000);
+        assert_eq!(sync(&mut file, 7, Some(&header)).unwrap().unwrap().0, 6 + 417);
+
+        // linear toc of 100 frames
+        let mut first = frames(1);
+        first[36..40].copy_from_slice(b"Xing");
+        first[40..44].copy_from_slice(&7u32.to_be_bytes());
+        first[44..48].copy_from_slice(&100u32.to_be_bytes());
+        first[48..52].copy_from_slice(&41700u32.to_be_bytes());
+        for i in 0..100 {
+            first[52 + i] = (i * 256 / 100) as u8;
+        }
+        first.extend(frames(1));
+        let stream = Stream::find(&mut Cursor::new(first), 0).unwrap().unwrap();
+        let half = Duration::from_secs_f64(100.0 * 1152.0 / 44100.0 / 2.0);
+        assert!((20800..=20850).contains(&stream.offset(half)));
+    }
+}
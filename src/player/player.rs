@@ -1,11 +1,24 @@
+use failure::err_msg;
 use futures::channel::oneshot;
-use std::path::PathBuf;
-use tempfile::NamedTempFile;
-use super::fetch::fetch_data;
+use std::path::{Path, PathBuf};
+use super::ahead;
+use super::ahead::Fill;
+use super::cache::Cache;
+use super::decoder;
+use super::loudness::{analyse, Loudness};
+use super::sink::Backend;
+use super::tempo::{Speeds, Tempo};
+use super::fetch::{fetch_data, Progress};
 use super::track::Track;
+use super::{Detail, Media, PlayerCommand, PlayerEvent};
 
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::fs;
+use std::time::{Duration, Instant};
+use rodio::Source;
 
 #[allow(unused)]
 pub enum PlayerState {
@@ -13,14 +26,12 @@ pub enum PlayerState {
     Paused {
         // start_of_track: oneshot::Sender<String>,
         // end_of_track: oneshot::Sender<()>,
-        // normalisation_factor: f32,
         // stream_loader_controller: StreamLoaderController,
         // bytes_per_second: usize,
     },
     Playing {
         // start_of_track: oneshot::Sender<String>,
         // end_of_track: oneshot::Sender<()>,
-        // normalisation_factor: f32,
         // // stream_loader_controller: StreamLoaderController,
         // bytes_per_second: usize,
     },
@@ -30,12 +41,68 @@ pub enum PlayerState {
     Invalid,
 }
 
+// track waiting for the first chunk of download
+struct Loading {
+    media: Media,
+    detail: Detail,
+    crossfade: bool,
+    file: PathBuf,
+    // none if the file is complete
+    progress: Option<Arc<Progress>>,
+    first_chunk: oneshot::Receiver<String>,
+    start_playing: bool,
+}
+
+impl Loading {
+    // stop the download and remove the file
+    fn remove(self) {
+        if let Some(progress) = &self.progress {
+            progress.cancel();
+        }
+        fs::remove_file(self.file).ok();
+    }
+}
+
+// next track downloaded while current track is playing
+enum Preload {
+    Loading(Loading),
+    Ready {
+        media: Media,
+        track: Track,
+        // appended to sink after current track
+        queued: bool,
+        crossfade: bool,
+    },
+}
+
+// previous track fading out in its own sink during crossfade
+struct Fading {
+    sink: rodio::Sink,
+    track: Track,
+    start: Instant,
+    duration: Duration,
+}
+
+// append next track to sink when current track remains less than this
+const PRELOAD_AHEAD: Duration = Duration::from_secs(5);
+
 pub struct Player {
-    // commands: Option<std::sync::mpsc::Sender<PlayerCommand>>,
-    endpoint: rodio::Device,
+    backend: Box<dyn Backend>,
     pub state: PlayerState,
     pub current: Option<Track>,
     pub sink: rodio::Sink,
+    url: String,
+    loading: Option<Loading>,
+    next: Option<Preload>,
+    fading: Option<Fading>,
+    crossfade: Duration,
+    cache: Arc<Mutex<Cache>>,
+    loudness: Arc<Mutex<Loudness>>,
+    speeds: Speeds,
+    events: Sender<PlayerEvent>,
+    // file and error of failed downloads
+    failed_tx: Sender<(PathBuf, String)>,
+    failed: Receiver<(PathBuf, String)>,
 }
 
 // player
@@ -43,130 +110,643 @@ impl Player {
     // new player
     pub fn new<>(
         // audio_filter: Option<Box<AudioFilter + Send>>,
-        // sink_builder: F,
-    ) -> Player
-    // where
-        // F: FnOnce() -> Box<dyn Sink> + Send + 'static,
-    {
-        let endpoint =
-            rodio::default_output_device().expect("Failed to find default music endpoint");
-        let sink = rodio::Sink::new(&endpoint);
+        backend: Box<dyn Backend>,
+        events: Sender<PlayerEvent>,
+        crossfade: Duration,
+        cache: Arc<Mutex<Cache>>,
+        loudness: Arc<Mutex<Loudness>>,
+        speeds: Speeds,
+    ) -> Player {
+        let sink = backend.sink();
+        let (failed_tx, failed) = mpsc::channel();
 
         Player {
             state: PlayerState::Stopped,
             current: None,
             sink: sink,
-            endpoint: endpoint,
+            backend,
+            url: String::new(),
+            loading: None,
+            next: None,
+            fading: None,
+            crossfade,
+            cache,
+            loudness,
+            speeds,
+            events,
+            failed_tx,
+            failed,
+        }
+    }
+
+    // player thread loop, run until the command sender is dropped
+    pub fn run(&mut self, commands: Receiver<PlayerCommand>) {
+        loop {
+            match commands.recv_timeout(Duration::from_millis(50)) {
+                Ok(cmd) => self.command(cmd),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.check_failed();
+            self.check_loading();
+            self.check_preload();
+            self.check_fading();
+            self.check_end_of_track();
         }
     }
 
     // run command
-    // fn command(&self, cmd: PlayerCommand) {
-        // self.commands.as_ref().expect("commands error").send(cmd).expect("send error");
-    // }
+    fn command(&mut self, cmd: PlayerCommand) {
+        match cmd {
+            PlayerCommand::Load(media, detail) => self.load(media, detail, true),
+            PlayerCommand::Preload(media, crossfade, detail) => {
+                self.preload(media, crossfade, detail)
+            }
+            PlayerCommand::ClearPreload => self.drop_preload(),
+            PlayerCommand::Play => self.play(),
+            PlayerCommand::Pause => self.pause(),
+            PlayerCommand::PlayPause => {
+                if self.status() {
+                    self.pause()
+                } else {
+                    self.play()
+                }
+            }
+            PlayerCommand::Stop => self.stop(),
+            PlayerCommand::Seek(offset) => {
+                let position = self.position().as_millis() as i64 + offset;
+                self.seek(position.max(0) as u64);
+            }
+            PlayerCommand::Position(_, position) => self.seek(position),
+            PlayerCommand::Volume(volume) => self.set_volume(volume),
+            PlayerCommand::Device(name) => self.set_device(name),
+            PlayerCommand::Speed(speed) => self.set_speed(speed),
+            // these are handled by app
+            PlayerCommand::Next
+            | PlayerCommand::Previous
+            | PlayerCommand::Sleep(_)
+            | PlayerCommand::Metadata(_, _) => {}
+        }
+    }
+
+    fn send(&self, event: PlayerEvent) {
+        self.events.send(event).ok();
+    }
 
     pub fn load(
         &mut self,
-        url: String,
+        media: Media,
+        detail: Detail,
         start_playing: bool,
     ) {
-        match &self.current {
-            Some(track) => {
-                fs::remove_file(track.file()).ok();
-                self.start();
+        if let Some(track) = self.current.take() {
+            track.remove();
+        }
+        if let Some(loading) = self.loading.take() {
+            loading.remove();
+        }
+        self.clear_preload();
+        self.finish_fading();
+        self.start();
+        self.state = PlayerState::Stopped;
+
+        match self.fetch(media, detail, start_playing, false) {
+            Ok(loading) => {
+                self.loading = Some(loading);
+                self.send(PlayerEvent::Buffering);
             }
-            None => {}
+            Err(e) => self.send(PlayerEvent::Error(e.to_string())),
         }
+    }
 
-        let buffer = NamedTempFile::new().unwrap();
-        let path = buffer.path().to_string_lossy().to_string();
-        let pathbuf = PathBuf::from(path);
+    // get the file of media, cached song is ready at once
+    // others start download thread of url
+    fn fetch(
+        &self,
+        media: Media,
+        detail: Detail,
+        start_playing: bool,
+        crossfade: bool,
+    ) -> Result<Loading, failure::Error> {
+        let (ptx, prx) = oneshot::channel::<String>();
+        let (file, progress) = match &media {
+            Media::Url(url) => self.download(url, None, &detail, ptx)?,
+            Media::File(file) => {
+                self.spawn_measure_loudness(file, &detail);
+                let file = self.cache.lock().unwrap().link_temp(file)?;
+                ptx.send("ok".to_owned()).ok();
+                (file, None)
+            }
+            Media::Song { id, br, url } => {
+                let cached = self.cache.lock().unwrap().open(*id, *br);
+                match (cached, url) {
+                    (Some(file), _) => {
+                        debug!("play cached {}", media);
+                        self.spawn_measure_loudness(&file, &detail);
+                        ptx.send("ok".to_owned()).ok();
+                        (file, None)
+                    }
+                    (None, Some(url)) => self.download(url, Some((*id, *br)), &detail, ptx)?,
+                    (None, None) => return Err(err_msg("song is not cached")),
+                }
+            }
+        };
+        Ok(Loading {
+            media,
+            detail,
+            crossfade,
+            file,
+            progress,
+            first_chunk: prx,
+            start_playing,
+        })
+    }
 
-        let (ptx, mut prx) = oneshot::channel::<String>();
+    // download url to a temp file, song is added to cache when finished
+    fn download(
+        &self,
+        url: &str,
+        song: Option<(i64, u32)>,
+        detail: &Detail,
+        tx: oneshot::Sender<String>,
+    ) -> Result<(PathBuf, Option<Arc<Progress>>), failure::Error> {
+        // keep the temp file after download, seek reopens it by path
+        // and it is removed when next song loads
+        let (buffer, pathbuf) = self.cache.lock().unwrap().temp_file()?;
 
+        let fetch_url = url.to_owned();
+        let file = pathbuf.to_owned();
+        let cache = self.cache.clone();
+        let loudness = self.loudness.clone();
+        let detail = detail.to_owned();
+        let failed = self.failed_tx.clone();
+        // shared with the decoder, which waits for bytes not downloaded yet
+        let progress = Arc::new(Progress::default());
+        let fetch_progress = progress.clone();
         thread::spawn(move || {
-            fetch_data(&url.to_owned(), buffer, ptx).expect("error thread task");
+            match fetch_data(&fetch_url, buffer, tx, fetch_progress.clone()) {
+                Ok(_) => {
+                    if let Some((id, br)) = song {
+                        cache.lock().unwrap().insert(id, br, &file);
+                    }
+                    Player::measure_loudness(&loudness, &file, &detail);
+                }
+                // track is dropped by player
+                Err(_) if fetch_progress.is_cancelled() => {}
+                Err(e) => {
+                    error!("download {} failed {}", fetch_url, e);
+                    failed.send((file, e.to_string())).ok();
+                }
+            }
         });
-        if start_playing {
-            loop {
-                match prx.try_recv() {
-                    Ok(p) => {
-                        match p {
-                            Some(_) => {
-                                match Track::load(pathbuf) {
-                                    Ok(track) => {
-                                        let mut track = track;
-                                        self.load_track(track.clone(), start_playing);
-                                        track.resume();
-                                        self.current = Some(track);
-                                        self.state = PlayerState::Playing{};
-                                    }
-                                    Err(_) => {}
+        Ok((pathbuf, Some(progress)))
+    }
+
+    // download next track for gapless playback or crossfade
+    pub fn preload(&mut self, media: Media, crossfade: bool, detail: Detail) {
+        match &self.next {
+            Some(Preload::Loading(loading)) if loading.media.is_same(&media) => return,
+            Some(Preload::Ready { media: next, .. }) if next.is_same(&media) => return,
+            _ => {}
+        }
+        self.drop_preload();
+        match self.fetch(media, detail, false, crossfade) {
+            Ok(loading) => self.next = Some(Preload::Loading(loading)),
+            Err(e) => error!("preload track failed {}", e),
+        }
+    }
+
+    fn drop_preload(&mut self) {
+        let queued = match &self.next {
+            Some(Preload::Ready { queued, .. }) => *queued,
+            _ => false,
+        };
+        self.clear_preload();
+        if queued {
+            // next track is already in sink, rebuild sink without it
+            let position = self.position();
+            self.seek(position.as_millis() as u64);
+        }
+    }
+
+    fn clear_preload(&mut self) {
+        match self.next.take() {
+            Some(Preload::Loading(loading)) => loading.remove(),
+            Some(Preload::Ready { track, .. }) => track.remove(),
+            None => {}
+        }
+    }
+
+    // real time left of current track
+    fn remaining(&self) -> Duration {
+        match &self.current {
+            Some(track) => track
+                .duration
+                .checked_sub(track.elapsed())
+                .unwrap_or_else(|| Duration::from_millis(0))
+                .div_f32(track.clock.speed()),
+            None => Duration::from_millis(0),
+        }
+    }
+
+    // append preloaded track to sink before current track ends
+    // and switch to it when current track is finished
+    fn check_preload(&mut self) {
+        match self.next.take() {
+            Some(Preload::Loading(mut loading)) => match loading.first_chunk.try_recv() {
+                Ok(Some(_)) => match Track::load(
+                    loading.file,
+                    loading.media.format(),
+                    loading.detail,
+                    loading.progress,
+                ) {
+                    Ok(track) => {
+                        track.clock.set_speed(self.speeds.speed(&track.detail));
+                        self.next = Some(Preload::Ready {
+                            media: loading.media,
+                            track,
+                            queued: false,
+                            crossfade: loading.crossfade,
+                        });
+                    }
+                    Err(e) => error!("preload track failed {}", e),
+                },
+                Ok(None) => {
+                    self.next = Some(Preload::Loading(loading));
+                }
+                Err(_) => {
+                    loading.remove();
+                    error!("preload fetch track data failed");
+                }
+            },
+            Some(Preload::Ready {
+                media,
+                mut track,
+                mut queued,
+                crossfade,
+            }) => {
+                if !queued && self.state.is_playing() && self.current.is_some() {
+                    let remaining = self.remaining();
+                    let crossfade = crossfade && self.crossfade.as_millis() > 0;
+                    if crossfade && !self.sink.empty() {
+                        if remaining <= self.crossfade {
+                            match self.start_crossfade(&mut track, remaining) {
+                                Ok(_) => {
+                                    self.send(PlayerEvent::TrackChanged(
+                                        track.duration,
+                                        track.progress.clone(),
+                                        track.clock.clone(),
+                                    ));
+                                    self.current = Some(track);
+                                    self.url = media.to_string();
                                 }
-                                break;
+                                Err(e) => {
+                                    error!("crossfade track decode failed {}", e);
+                                    track.remove();
+                                }
+                            }
+                            return;
+                        }
+                    } else if remaining < PRELOAD_AHEAD || self.sink.empty() {
+                        match self.decode(&mut track, Duration::from_millis(0)) {
+                            Ok(source) => {
+                                self.sink.append(source);
+                                queued = true;
+                            }
+                            Err(e) => {
+                                error!("preload track decode failed {}", e);
+                                track.remove();
+                                return;
                             }
-                            None => {}
                         }
                     }
-                    Err(_) => {}
+                } else if queued && self.sink.len() <= 1 {
+                    // current track is finished, the preloaded one is playing
+                    if let Some(finished) = self.current.take() {
+                        finished.remove();
+                    }
+                    self.send(PlayerEvent::TrackChanged(
+                        track.duration,
+                        track.progress.clone(),
+                        track.clock.clone(),
+                    ));
+                    self.current = Some(track);
+                    self.url = media.to_string();
+                    return;
                 }
+                self.next = Some(Preload::Ready {
+                    media,
+                    track,
+                    queued,
+                    crossfade,
+                });
             }
+            None => {}
         }
     }
 
-    pub fn load_track(&mut self, track: Track, playing: bool) {
-        if playing {
-            let path = track.file.to_string_lossy().to_string();
-            let f = std::fs::File::open(&path).unwrap();
-            let source = rodio::Decoder::new(std::io::BufReader::new(f)).unwrap();
+    // new source of track from start
+    fn decode(
+        &self,
+        track: &mut Track,
+        start: Duration,
+    ) -> Result<Box<dyn Source<Item = i16> + Send>, failure::Error> {
+        // reads of a file in download wait in the decode thread, not in the output
+        let source = ahead::decode(decoder::open(track.open()?, track.format, start)?);
+        let ended = source.ended();
+        // loudness of new song is measured after download, use it if ready
+        let gain = match track.gain {
+            Some(gain) => gain,
+            None => self
+                .loudness
+                .lock()
+                .unwrap()
+                .gain(track.detail.id, track.detail.album),
+        };
+        track.gain = Some(gain);
+        // position of track moves with the samples played, so it's not scaled by speed
+        let source = track.count(source.amplify(gain), start);
+        Ok(Box::new(Tempo::new(Fill::new(source, ended), track.clock.clone())))
+    }
+
+    fn spawn_measure_loudness(&self, file: &Path, detail: &Detail) {
+        let loudness = self.loudness.clone();
+        let file = file.to_path_buf();
+        let detail = detail.to_owned();
+        thread::spawn(move || Player::measure_loudness(&loudness, &file, &detail));
+    }
+
+    // measure loudness of finished song for normalisation
+    // it decodes the whole file, so run it in a thread
+    fn measure_loudness(loudness: &Mutex<Loudness>, file: &Path, detail: &Detail) {
+        let id = match detail.id {
+            Some(id) if loudness.lock().unwrap().needs(id) => id,
+            _ => return,
+        };
+        match analyse(file, detail.album) {
+            Ok(entry) => loudness.lock().unwrap().insert(id, entry),
+            Err(e) => error!("measure loudness of song {} failed {}", id, e),
+        }
+    }
+
+    // play next track in a new sink with fade in
+    // and fade out current sink in the rest time of current track
+    fn start_crossfade(&mut self, next: &mut Track, duration: Duration) -> Result<(), failure::Error> {
+        let source = self.decode(next, Duration::from_millis(0))?;
+        self.finish_fading();
+        let sink = self.backend.sink();
+        sink.set_volume(self.sink.volume());
+        sink.append(source.fade_in(duration));
+        let previous = std::mem::replace(&mut self.sink, sink);
+        if let Some(current) = self.current.take() {
+            self.fading = Some(Fading {
+                sink: previous,
+                track: current,
+                start: Instant::now(),
+                duration,
+            });
+        }
+        Ok(())
+    }
+
+    // lower the volume of fading sink step by step
+    fn check_fading(&mut self) {
+        let done = match &self.fading {
+            Some(fading) => {
+                let elapsed = fading.start.elapsed();
+                if elapsed >= fading.duration || fading.sink.empty() {
+                    true
+                } else {
+                    let left = 1.0 - elapsed.as_secs_f32() / fading.duration.as_secs_f32();
+                    fading.sink.set_volume(self.sink.volume() * left);
+                    false
+                }
+            }
+            None => false,
+        };
+        if done {
+            self.finish_fading();
+        }
+    }
+
+    // stop fading sink at once, used when pause, seek or load
+    fn finish_fading(&mut self) {
+        if let Some(fading) = self.fading.take() {
+            fading.sink.stop();
+            fading.track.remove();
+        }
+    }
 
+    // stop the track whose download is broken after retries
+    // failed one may be replaced by another track already
+    fn check_failed(&mut self) {
+        while let Ok((file, e)) = self.failed.try_recv() {
+            if self.loading.as_ref().is_some_and(|loading| loading.file == file) {
+                if let Some(loading) = self.loading.take() {
+                    loading.remove();
+                }
+                self.send(PlayerEvent::Error(e));
+            } else if self.current.as_ref().is_some_and(|track| track.file == file) {
+                self.stop();
+                self.send(PlayerEvent::Error(e));
+            } else {
+                let (preloaded, queued) = match &self.next {
+                    Some(Preload::Loading(loading)) => (loading.file == file, false),
+                    Some(Preload::Ready { track, queued, .. }) => (track.file == file, *queued),
+                    None => (false, false),
+                };
+                if preloaded {
+                    error!("preload track failed {}", e);
+                    self.clear_preload();
+                    if queued {
+                        // rebuild sink without the broken track
+                        let position = self.position();
+                        self.seek(position.as_millis() as u64);
+                    }
+                }
+            }
+        }
+    }
+
+    // check if the loading track get first chunk
+    fn check_loading(&mut self) {
+        let mut loading = match self.loading.take() {
+            Some(loading) => loading,
+            None => return,
+        };
+        match loading.first_chunk.try_recv() {
+            Ok(Some(_)) => match Track::load(
+                loading.file,
+                loading.media.format(),
+                loading.detail,
+                loading.progress,
+            ) {
+                Ok(mut track) => match self.load_track(&mut track, loading.start_playing) {
+                    Ok(_) => {
+                        self.send(PlayerEvent::Loaded(
+                            track.duration,
+                            track.progress.clone(),
+                            track.clock.clone(),
+                        ));
+                        if loading.start_playing {
+                            self.state = PlayerState::Playing{};
+                            self.send(PlayerEvent::Playing);
+                        } else {
+                            self.state = PlayerState::Paused{};
+                            self.send(PlayerEvent::Paused);
+                        }
+                        self.current = Some(track);
+                        self.url = loading.media.to_string();
+                    }
+                    Err(e) => {
+                        track.remove();
+                        self.send(PlayerEvent::Error(e.to_string()));
+                    }
+                },
+                Err(e) => self.send(PlayerEvent::Error(e.to_string())),
+            },
+            Ok(None) => {
+                self.loading = Some(loading);
+            }
+            Err(_) => {
+                // fetch thread is gone before first chunk
+                loading.remove();
+                self.send(PlayerEvent::Error("fetch track data failed".to_owned()));
+            }
+        }
+    }
+
+    // sink runs dry means current track is finished
+    fn check_end_of_track(&mut self) {
+        if self.state.is_playing() && self.current.is_some() && self.sink.empty() {
+            self.state = PlayerState::EndOfTrack {
+                url: self.url.to_owned(),
+            };
+            debug!("end of track {}", self.url);
+            self.send(PlayerEvent::EndOfTrack);
+        }
+    }
+
+    pub fn load_track(&mut self, track: &mut Track, playing: bool) -> Result<(), failure::Error> {
+        track.clock.set_speed(self.speeds.speed(&track.detail));
+        let source = self.decode(track, Duration::from_millis(0))?;
+
+        if playing {
             self.sink.play();
-            self.sink.append(source);
+        } else {
+            self.sink.pause();
+        }
+        self.sink.append(source);
+        Ok(())
+    }
+
+    // seek current track to position on a new sink
+    pub fn seek(&mut self, position_ms: u64) {
+        let mut track = match self.current.take() {
+            Some(track) => track,
+            None => return,
+        };
+        let position = Duration::from_millis(position_ms).min(track.duration);
+        self.finish_fading();
+        match self.decode(&mut track, position) {
+            Ok(source) => {
+                self.start();
+                if !self.state.is_playing() {
+                    self.sink.pause();
+                }
+                self.sink.append(source);
+                // new sink doesn't have the preloaded track
+                if let Some(Preload::Ready { queued, .. }) = &mut self.next {
+                    *queued = false;
+                }
+                if self.state.is_playing() {
+                    self.send(PlayerEvent::Playing);
+                } else {
+                    self.send(PlayerEvent::Paused);
+                }
+            }
+            Err(e) => {
+                error!("seek track failed {}", e);
+            }
+        }
+        self.current = Some(track);
+    }
+
+    // rebuild sink on new device, seek keeps position and volume
+    fn set_device(&mut self, name: String) {
+        if let Err(e) = self.backend.select(&name) {
+            self.send(PlayerEvent::Error(e.to_string()));
+            return;
+        }
+        if self.current.is_some() {
+            let position = self.position();
+            self.seek(position.as_millis() as u64);
+        } else {
+            self.start();
+        }
+        self.send(PlayerEvent::DeviceChanged(name));
+    }
+
+    // speed of dj program, kept for the program and its radio
+    fn set_speed(&mut self, speed: f32) {
+        if let Some(track) = &self.current {
+            track.clock.set_speed(speed);
+            self.speeds.insert(&track.detail, speed);
         }
     }
 
     pub fn start(&mut self) {
         let vol = self.sink.volume();
         self.sink.stop();
-        self.sink = rodio::Sink::new(&self.endpoint);
+        self.sink = self.backend.sink();
         self.set_volume(vol);
     }
 
     pub fn play(&mut self) {
+        if self.current.is_none() {
+            // play after first chunk arrives
+            if let Some(loading) = &mut self.loading {
+                loading.start_playing = true;
+            }
+            return;
+        }
         self.sink.play();
         self.state = PlayerState::Playing{};
-        self.current = self.current.take().and_then(|mut s| {
-            s.resume();
-            Some(s)
-        });
+        self.send(PlayerEvent::Playing);
     }
 
     pub fn pause(&mut self) {
+        if self.current.is_none() {
+            if let Some(loading) = &mut self.loading {
+                loading.start_playing = false;
+            }
+            return;
+        }
         self.sink.pause();
+        self.finish_fading();
         self.state = PlayerState::Paused{};
-        self.current = self.current.take().and_then(|mut s| {
-            s.stop();
-            Some(s)
-        });
+        self.send(PlayerEvent::Paused);
     }
 
-    pub fn stop(&self) {
-        self.sink.stop()
+    pub fn stop(&mut self) {
+        self.sink.stop();
+        self.finish_fading();
+        self.state = PlayerState::Stopped;
+        self.send(PlayerEvent::Stopped);
     }
 
-    #[allow(unused)]
-    pub fn seek(&self, position_ms: u32) {
-        // self.command(PlayerCommand::Seek(position_ms));
+    // elapsed time of current track
+    pub fn position(&self) -> Duration {
+        match &self.current {
+            Some(track) => track.elapsed(),
+            None => Duration::from_millis(0),
+        }
     }
 
     pub fn status(&self) -> bool {
         self.state.is_playing()
     }
 
-    pub fn get_volume(&self) -> f32 {
-        self.sink.volume()
-    }
-
     pub fn set_volume(&self, volume: f32) {
         self.sink.set_volume(volume)
     }
@@ -177,6 +757,14 @@ impl Drop for Player {
     fn drop(&mut self) {
         debug!("Shutting down player thread ...");
         // remove cache file
+        if let Some(track) = &self.current {
+            track.remove();
+        }
+        if let Some(loading) = self.loading.take() {
+            loading.remove();
+        }
+        self.clear_preload();
+        self.finish_fading();
     }
 }
 
@@ -0,0 +1,286 @@
+use failure::err_msg;
+use rodio::dynamic_mixer::{mixer, DynamicMixer, DynamicMixerController};
+use rodio::{DeviceTrait, Sink};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// pcm format of pipe and wav backends
+const CHANNELS: u16 = 2;
+const SAMPLE_RATE: u32 = 44100;
+// samples of 10ms
+const CHUNK: usize = (SAMPLE_RATE / 100) as usize * CHANNELS as usize;
+
+// where the player sends sound
+pub trait Backend {
+    // new sink playing to this backend
+    fn sink(&self) -> Sink;
+
+    // switch output device by name, sinks made before keep playing to the old one
+    fn select(&mut self, _name: &str) -> Result<(), failure::Error> {
+        Err(err_msg("audio backend has no device to select"))
+    }
+}
+
+// sound card
+struct Device(rodio::Device);
+
+impl Backend for Device {
+    fn sink(&self) -> Sink {
+        Sink::new(&self.0)
+    }
+
+    fn select(&mut self, name: &str) -> Result<(), failure::Error> {
+        self.0 = output_device(Some(name))?;
+        Ok(())
+    }
+}
+
+// output device by name, default one if name is none
+fn output_device(name: Option<&str>) -> Result<rodio::Device, failure::Error> {
+    match name {
+        Some(name) => rodio::output_devices()?
+            .find(|device| device.name().ok().as_deref() == Some(name))
+            .ok_or_else(|| format_err!("audio device {} not found", name)),
+        None => rodio::default_output_device().ok_or_else(|| err_msg("no audio output device")),
+    }
+}
+
+// names of output devices of sound card
+pub fn devices() -> Vec<String> {
+    match rodio::output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            error!("list audio devices failed {}", e);
+            vec![]
+        }
+    }
+}
+
+enum Output {
+    Raw(Box<dyn Write + Send>),
+    Wav(hound::WavWriter<BufWriter<File>>),
+}
+
+impl Output {
+    fn write(&mut self, samples: &[i16]) -> Result<(), failure::Error> {
+        match self {
+            Output::Raw(writer) => {
+                let bytes = samples
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes().to_vec())
+                    .collect::<Vec<u8>>();
+                writer.write_all(&bytes)?;
+                writer.flush()?;
+            }
+            Output::Wav(writer) => {
+                for sample in samples {
+                    writer.write_sample(*sample)?;
+                }
+                // header keeps the right length if app is killed
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) {
+        if let Output::Wav(writer) = self {
+            if let Err(e) = writer.finalize() {
+                error!("finish wav file failed {}", e);
+            }
+        }
+    }
+}
+
+// mix sinks and write 16 bit little endian pcm in real time
+// sinks play silence when paused or empty as they do on sound card
+struct Writer {
+    controller: Arc<DynamicMixerController<f32>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Writer {
+    fn new(output: Output) -> Writer {
+        let (controller, source) = mixer(CHANNELS, SAMPLE_RATE);
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread = thread::spawn(move || Writer::run(source, output, thread_running));
+        Writer {
+            controller,
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(mut source: DynamicMixer<f32>, mut output: Output, running: Arc<AtomicBool>) {
+        let mut chunk = Vec::with_capacity(CHUNK);
+        let mut start = Instant::now();
+        let mut written = 0;
+        while running.load(Ordering::Relaxed) {
+            chunk.clear();
+            chunk.extend(
+                source
+                    .by_ref()
+                    .take(CHUNK)
+                    .map(|sample| (sample.clamp(-1.0, 1.0) * 32767.0) as i16),
+            );
+            if chunk.is_empty() {
+                // no sink
+                thread::sleep(Duration::from_millis(10));
+                start = Instant::now();
+                written = 0;
+                continue;
+            }
+            if let Err(e) = output.write(&chunk) {
+                // keep pulling sinks, so player still gets end of tracks
+                error!("write audio output failed {}", e);
+                output = Output::Raw(Box::new(io::sink()));
+            }
+            written += chunk.len() as u64;
+            // pull samples in real time as sound card does, position of track counts them
+            let played = Duration::from_micros(
+                written * 1_000_000 / (u64::from(SAMPLE_RATE) * u64::from(CHANNELS)),
+            );
+            if let Some(wait) = played.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        output.finish();
+    }
+}
+
+impl Backend for Writer {
+    fn sink(&self) -> Sink {
+        let (sink, source) = Sink::new_idle();
+        self.controller.add(source);
+        sink
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+// find backend by name
+// rodio: sound card, pipe:<path>: raw pcm to file or fifo
+// wav:<path>: wav file, null: play to nowhere
+// device is the sound card device of rodio
+pub fn find(name: &str, device: Option<&str>) -> Result<Box<dyn Backend>, failure::Error> {
+    let (kind, path) = match name.find(':') {
+        Some(index) => (&name[..index], Some(&name[index + 1..])),
+        None => (name, None),
+    };
+    match (kind, path) {
+        ("rodio", None) => {
+            let device = output_device(device).or_else(|e| {
+                // device in config may be unplugged
+                error!("{}, use default device", e);
+                output_device(None)
+            })?;
+            Ok(Box::new(Device(device)))
+        }
+        // stdout is the terminal of ui
+        ("pipe", None) | ("pipe", Some("-")) | ("pipe", Some("")) => Err(err_msg(
+            "pipe backend needs a file or fifo, stdout is used by the ui",
+        )),
+        ("pipe", Some(path)) => {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            Ok(Box::new(Writer::new(Output::Raw(Box::new(file)))))
+        }
+        ("wav", Some(path)) => {
+            let spec = hound::WavSpec {
+                channels: CHANNELS,
+                sample_rate: SAMPLE_RATE,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            Ok(Box::new(Writer::new(Output::Wav(hound::WavWriter::create(
+                path, spec,
+            )?))))
+        }
+        ("null", None) => Ok(null()),
+        _ => Err(format_err!("unknown audio backend {}", name)),
+    }
+}
+
+// used when the backend in config can't be opened
+pub fn null() -> Box<dyn Backend> {
+    Box::new(Writer::new(Output::Raw(Box::new(io::sink()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+    use rodio::Source;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn play(backend: &dyn Backend) {
+        let sink = backend.sink();
+        sink.append(SineWave::new(440).take_duration(Duration::from_millis(50)));
+        let start = Instant::now();
+        while !sink.empty() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(sink.empty());
+    }
+
+    #[test]
+    fn test_pipe_backend() {
+        let data = Shared::default();
+        let backend = Writer::new(Output::Raw(Box::new(data.clone())));
+        let start = Instant::now();
+        play(&backend);
+        // played in real time
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        drop(backend);
+        let bytes = data.0.lock().unwrap();
+        assert!(bytes.len() >= CHUNK * 2 * 4);
+        assert!(bytes.iter().any(|byte| *byte != 0));
+    }
+
+    #[test]
+    fn test_wav_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.wav");
+        let backend = find(&format!("wav:{}", path.display()), None).unwrap();
+        play(backend.as_ref());
+        drop(backend);
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, CHANNELS);
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+        assert!(reader.len() as usize >= CHUNK * 4);
+        assert!(find("speaker", None).is_err());
+        assert!(find("pipe", None).is_err());
+        assert!(find("pipe:-", None).is_err());
+    }
+}
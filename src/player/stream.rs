@@ -0,0 +1,134 @@
+use super::fetch::Progress;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use symphonia::core::io::MediaSource;
+
+// poll interval while waiting for downloaded bytes
+const WAIT: Duration = Duration::from_millis(20);
+
+// file of a track that may still be downloading
+// reads wait until the bytes are written, the end of file is the end of download
+pub struct StreamFile {
+    file: File,
+    // none for cached and local files which are complete
+    progress: Option<Arc<Progress>>,
+    position: u64,
+}
+
+impl StreamFile {
+    pub fn open(path: &Path, progress: Option<Arc<Progress>>) -> io::Result<StreamFile> {
+        Ok(StreamFile {
+            file: File::open(path)?,
+            progress,
+            position: 0,
+        })
+    }
+
+    // bytes readable after position, waits for download and 0 at the end
+    fn available(&self) -> u64 {
+        let progress = match &self.progress {
+            Some(progress) => progress,
+            None => return u64::MAX,
+        };
+        loop {
+            let downloaded = progress.downloaded();
+            // cancelled track is dropped by player, don't hold the decode thread
+            if downloaded > self.position || progress.is_finished() || progress.is_cancelled() {
+                return downloaded.saturating_sub(self.position);
+            }
+            thread::sleep(WAIT);
+        }
+    }
+
+    // length of the whole file, waits for the end of download if server doesn't tell it
+    fn len(&self) -> io::Result<u64> {
+        let progress = match &self.progress {
+            Some(progress) => progress,
+            None => return Ok(self.file.metadata()?.len()),
+        };
+        loop {
+            if let Some(total) = progress.total() {
+                return Ok(total);
+            }
+            if progress.is_finished() || progress.is_cancelled() {
+                return Ok(progress.downloaded());
+            }
+            thread::sleep(WAIT);
+        }
+    }
+}
+
+impl Read for StreamFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.available();
+        let len = (buf.len() as u64).min(available) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let read = self.file.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for StreamFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.len()?.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file"))?;
+        // reads after the downloaded bytes wait for them
+        self.position = self.file.seek(SeekFrom::Start(position))?;
+        Ok(self.position)
+    }
+}
+
+impl MediaSource for StreamFile {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        match &self.progress {
+            Some(progress) => progress.total(),
+            None => self.file.metadata().ok().map(|metadata| metadata.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_read_waits_for_download() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let progress = Arc::new(Progress::default());
+        let mut stream = StreamFile::open(file.path(), Some(progress.clone())).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let reader = thread::spawn(move || {
+            let mut data = vec![];
+            stream.read_to_end(&mut data).unwrap();
+            tx.send(()).unwrap();
+            data
+        });
+        file.write_all(b"first").unwrap();
+        progress.add_downloaded(5);
+        thread::sleep(Duration::from_millis(100));
+        // reader keeps waiting at the end of written data
+        assert!(rx.try_recv().is_err());
+        file.write_all(b" second").unwrap();
+        progress.add_downloaded(7);
+        progress.finish();
+        assert_eq!(reader.join().unwrap(), b"first second");
+    }
+}
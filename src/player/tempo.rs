@@ -0,0 +1,315 @@
+use super::track::Clock;
+use super::Detail;
+use rodio::Source;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const SPEED_FILE: &str = "speed.json";
+pub const MIN_SPEED: f32 = 0.75;
+pub const MAX_SPEED: f32 = 2.0;
+pub const SPEED_STEP: f32 = 0.25;
+// segment of overlap-add in ms, the output moves half of it each time
+const SEGMENT: u32 = 40;
+// range to search the most similar segment around its place in ms
+const SEEK: u32 = 10;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Entries {
+    // keyed by song id of program
+    programs: HashMap<i64, f32>,
+    radios: HashMap<i64, f32>,
+}
+
+// playback speed of dj programs, kept in cache dir
+pub struct Speeds {
+    file: PathBuf,
+    entries: Entries,
+}
+
+impl Speeds {
+    pub fn new(dir: &Path) -> Speeds {
+        let file = dir.join(SPEED_FILE);
+        let entries = fs::read_to_string(&file)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        Speeds { file, entries }
+    }
+
+    // speed of program, or the last one used in its radio
+    pub fn speed(&self, detail: &Detail) -> f32 {
+        let radio = match detail.radio {
+            Some(radio) => radio,
+            None => return 1.0,
+        };
+        detail
+            .id
+            .and_then(|id| self.entries.programs.get(&id))
+            .or_else(|| self.entries.radios.get(&radio))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    pub fn insert(&mut self, detail: &Detail, speed: f32) {
+        let radio = match detail.radio {
+            Some(radio) => radio,
+            None => return,
+        };
+        if let Some(id) = detail.id {
+            self.entries.programs.insert(id, speed);
+        }
+        self.entries.radios.insert(radio, speed);
+        match serde_json::to_string(&self.entries) {
+            Ok(data) => {
+                if let Err(e) = fs::write(&self.file, data) {
+                    error!("save speed failed {}", e);
+                }
+            }
+            Err(e) => error!("serialize speed failed {}", e),
+        }
+    }
+}
+
+// play source at the speed of clock and keep the pitch
+// segments of source are overlapped at a faster or slower pace, each one is
+// moved a little to where it looks most like the sound it overlaps (wsola)
+pub struct Tempo<S> {
+    source: S,
+    clock: Arc<Clock>,
+    channels: usize,
+    // frames of a segment
+    size: usize,
+    // frames to search around
+    seek: usize,
+    window: Vec<f32>,
+    // samples of source not played yet
+    input: Vec<f32>,
+    // place of next segment in input frames
+    position: f64,
+    // windowed second half of last segment
+    overlap: Vec<f32>,
+    // mono input under the overlap, next segment should look like it
+    reference: Vec<f32>,
+    output: VecDeque<i16>,
+    // samples passed through at normal speed, stretch starts at a frame
+    passed: usize,
+    stretching: bool,
+    ended: bool,
+}
+
+impl<S> Tempo<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(source: S, clock: Arc<Clock>) -> Tempo<S> {
+        let channels = usize::from(source.channels().max(1));
+        let rate = source.sample_rate().max(1);
+        let size = ((rate * SEGMENT / 1000) as usize / 2 * 2).max(4);
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+            .collect();
+        Tempo {
+            source,
+            clock,
+            channels,
+            size,
+            seek: (rate * SEEK / 1000) as usize,
+            window,
+            input: vec![],
+            position: 0.0,
+            overlap: vec![],
+            reference: vec![],
+            output: VecDeque::new(),
+            passed: 0,
+            stretching: false,
+            ended: false,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        let start = frame * self.channels;
+        self.input[start..start + self.channels].iter().sum::<f32>() / self.channels as f32
+    }
+
+    // frame in low..=high where the segment is most like the reference
+    fn most_similar(&self, low: usize, high: usize) -> usize {
+        let mut best = low;
+        let mut best_score = f32::MIN;
+        // every other frame is enough to find it
+        for start in (low..=high).step_by(2) {
+            let mut correlation = 0.0;
+            let mut energy = 0.0;
+            for (i, reference) in self.reference.iter().enumerate().step_by(2) {
+                let value = self.mono(start + i);
+                correlation += reference * value;
+                energy += value * value;
+            }
+            let score = correlation / energy.sqrt().max(1.0);
+            if score > best_score {
+                best = start;
+                best_score = score;
+            }
+        }
+        best
+    }
+
+    // overlap next segment and put half a segment to output, false at the end
+    fn stretch(&mut self) -> bool {
+        let hop = self.size / 2;
+        let channels = self.channels;
+        let start = self.position as usize;
+        while self.frames() < start + self.seek + self.size && !self.ended {
+            match self.source.next() {
+                Some(sample) => self.input.push(f32::from(sample)),
+                None => self.ended = true,
+            }
+        }
+        let frames = self.frames();
+        let low = start.saturating_sub(self.seek);
+        if frames < low + self.size {
+            // end of source, play the rest of last segment
+            let rest = std::mem::take(&mut self.overlap);
+            self.output.extend(rest.into_iter().map(to_sample));
+            return !self.output.is_empty();
+        }
+        let high = (start + self.seek).min(frames - self.size);
+        let first = self.reference.is_empty();
+        let best = if first {
+            start.min(high)
+        } else {
+            self.most_similar(low, high)
+        };
+        for i in 0..hop {
+            // first segment goes on from the samples passed through
+            let weight = if first { 1.0 } else { self.window[i] };
+            for channel in 0..channels {
+                let previous = self.overlap.get(i * channels + channel).copied();
+                let value = self.input[(best + i) * channels + channel] * weight;
+                self.output.push_back(to_sample(previous.unwrap_or(0.0) + value));
+            }
+        }
+        self.overlap = (hop..self.size)
+            .flat_map(|i| {
+                let frame = &self.input[(best + i) * channels..(best + i + 1) * channels];
+                let weight = self.window[i];
+                frame.iter().map(move |sample| sample * weight)
+            })
+            .collect();
+        self.reference = (best + hop..best + self.size)
+            .map(|frame| self.mono(frame))
+            .collect();
+        self.position += hop as f64 * f64::from(self.clock.speed());
+        // drop input before the next search range
+        let drop = (self.position as usize).saturating_sub(self.seek).min(frames);
+        self.input.drain(..drop * channels);
+        self.position -= drop as f64;
+        true
+    }
+}
+
+fn to_sample(value: f32) -> i16 {
+    value.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+}
+
+impl<S> Iterator for Tempo<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if !self.stretching {
+            // stretching starts at the first channel of a frame
+            let channel = self.passed % self.channels;
+            if channel != 0 || self.clock.speed() == 1.0 {
+                self.passed += 1;
+                return self.source.next();
+            }
+            self.stretching = true;
+        }
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(sample);
+            }
+            if !self.stretch() {
+                return None;
+            }
+        }
+    }
+}
+
+impl<S> Source for Tempo<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.stretching {
+            None
+        } else {
+            self.source.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use rodio::source::SineWave;
+
+    #[test]
+    fn test_tempo_changes_length() {
+        let samples = SineWave::new(440)
+            .take_duration(Duration::from_secs(1))
+            .map(|sample| (sample * 16384.0) as i16)
+            .collect::<Vec<i16>>();
+        let clock = Arc::new(Clock::default());
+        clock.set_speed(2.0);
+        let fast = Tempo::new(SamplesBuffer::new(1, 48000, samples.clone()), clock.clone());
+        let len = fast.count() as f32;
+        assert!((len / samples.len() as f32 - 0.5).abs() < 0.02);
+        clock.set_speed(0.75);
+        let slow = Tempo::new(SamplesBuffer::new(1, 48000, samples.clone()), clock);
+        let len = slow.count() as f32;
+        assert!((len / samples.len() as f32 - 1.0 / 0.75).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_speed_of_program_or_radio() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut speeds = Speeds::new(dir.path());
+        let program = |id| Detail {
+            id: Some(id),
+            radio: Some(7),
+            ..Default::default()
+        };
+        speeds.insert(&program(1), 1.5);
+        speeds.insert(&program(2), 1.25);
+        let speeds = Speeds::new(dir.path());
+        assert_eq!(speeds.speed(&program(1)), 1.5);
+        // new program of radio gets the last speed
+        assert_eq!(speeds.speed(&program(3)), 1.25);
+        assert_eq!(speeds.speed(&Detail::default()), 1.0);
+    }
+}
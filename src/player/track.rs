@@ -1,53 +1,162 @@
+use super::decoder;
+use super::decoder::Format;
+use super::fetch::Progress;
+use super::stream::StreamFile;
+use super::Detail;
+use failure::err_msg;
+use rodio::{Sample, Source};
 use std::convert::AsRef;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, PartialOrd, Ord)]
-pub enum Status {
-    Playing(::std::time::Instant, ::std::time::Duration),
-    Stopped(::std::time::Duration),
+// position of a track from the samples its source gave to the output
+// it stops when the sink is paused, the decoder stalls or the source ends
+#[derive(Debug)]
+pub struct Clock {
+    // micro seconds
+    position: AtomicU64,
+    // only the source of latest decode counts, older ones may be still in a stopped sink
+    generation: AtomicU64,
+    // playback speed, bits of f32
+    speed: AtomicU32,
 }
 
-
-impl Status {
-    // Time elapsed
-    pub fn elapsed(self) -> ::std::time::Duration {
-        match self {
-            Status::Stopped(d) => d,
-            Status::Playing(start, extra) => start.elapsed() + extra,
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock {
+            position: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+            speed: AtomicU32::new(1f32.to_bits()),
         }
     }
-    // stop
-    pub fn stop(&mut self) {
-        *self = match *self {
-            Status::Stopped(_) => *self,
-            Status::Playing(start, extra) => Status::Stopped(start.elapsed() + extra),
-        };
+}
+
+impl Clock {
+    pub fn position(&self) -> Duration {
+        Duration::from_micros(self.position.load(Ordering::Relaxed))
     }
-    // resume track
-    pub fn resume(&mut self) {
-        *self = match *self {
-            Status::Playing(_, _) => *self,
-            Status::Stopped(duration) => Status::Playing(::std::time::Instant::now(), duration),
-        };
+
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
     }
-    #[allow(unused)]
-    pub fn is_stopped(self) -> bool {
-        match self {
-            Status::Stopped(_) => true,
-            _ => false,
+
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    // count samples of source from start
+    fn count<S>(self: &Arc<Self>, source: S, start: Duration) -> Counted<S>
+    where
+        S: Source,
+        S::Item: Sample,
+    {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.position.store(start.as_micros() as u64, Ordering::Relaxed);
+        Counted {
+            channels: source.channels(),
+            sample_rate: source.sample_rate(),
+            source,
+            clock: self.clone(),
+            generation,
+            base: start,
+            samples: 0,
+        }
+    }
+}
+
+// source moving the clock as its samples are pulled by the sink
+pub struct Counted<S> {
+    source: S,
+    clock: Arc<Clock>,
+    generation: u64,
+    // position when channels or sample rate changed last time
+    base: Duration,
+    samples: u64,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl<S> Counted<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn played(&self) -> Duration {
+        let frames = self.samples / u64::from(self.channels.max(1));
+        self.base + Duration::from_micros(frames * 1_000_000 / u64::from(self.sample_rate.max(1)))
+    }
+}
+
+impl<S> Iterator for Counted<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let sample = self.source.next()?;
+        let (channels, sample_rate) = (self.source.channels(), self.source.sample_rate());
+        if channels != self.channels || sample_rate != self.sample_rate {
+            // mp3 frames may change format
+            self.base = self.played();
+            self.samples = 0;
+            self.channels = channels;
+            self.sample_rate = sample_rate;
         }
+        self.samples += 1;
+        // position is stored after the last sample of a frame
+        let channel = self.samples % u64::from(channels.max(1));
+        if channel == 0 && self.clock.generation.load(Ordering::Relaxed) == self.generation {
+            self.clock
+                .position
+                .store(self.played().as_micros() as u64, Ordering::Relaxed);
+        }
+        Some(sample)
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Debug, PartialOrd, Ord)]
+impl<S> Source for Counted<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Track {
     /// Duration of the song
     pub duration: Duration,
     /// File path to the song
     pub file: PathBuf,
-    /// Elapsed time of song playing or Start time
-    pub status: Status,
+    /// Audio format sniffed from the file
+    pub format: Format,
+    /// Song detail from api
+    pub detail: Detail,
+    /// Volume factor of normalisation, fixed when first decoded
+    pub gain: Option<f32>,
+    /// Download of the song, none if the file is complete
+    pub progress: Option<Arc<Progress>>,
+    /// Position of song played by the output
+    pub clock: Arc<Clock>,
 }
 
 impl Track {
@@ -58,32 +167,65 @@ impl Track {
     }
 
     pub fn elapsed(&self) -> Duration {
-        self.status.elapsed()
-    }
-    /// Pause the song
-    pub fn stop(&mut self) {
-        self.status.stop()
+        self.clock.position()
     }
-    /// Resume the song
-    pub fn resume(&mut self) {
-        self.status.resume()
-    }
-    /// Check if the song is stopped/paused
-    #[allow(unused)]
-    pub fn is_stopped(&self) -> bool {
-        self.status.is_stopped()
+    /// Count the samples of a new source of the song from start, the source of
+    /// last count stops moving the clock
+    pub fn count<S>(&self, source: S, start: Duration) -> Counted<S>
+    where
+        S: Source,
+        S::Item: Sample,
+    {
+        self.clock.count(source, start)
     }
     /// Returns the path of the song
+    #[allow(unused)]
     pub fn file(&self) -> &Path {
         &self.file
     }
+    /// Open the song for decoding, reads wait for the download
+    pub fn open(&self) -> std::io::Result<StreamFile> {
+        StreamFile::open(&self.file, self.progress.clone())
+    }
+    /// Stop the download and remove the file
+    pub fn remove(&self) {
+        if let Some(progress) = &self.progress {
+            progress.cancel();
+        }
+        fs::remove_file(&self.file).ok();
+    }
 
-    pub fn load(file: PathBuf) -> Result<Self, failure::Error > {
-        let duration = ::mp3_duration::from_path(&file).unwrap();
+    /// Load the song, format and duration are guessed from url or song detail
+    /// if the head of file is not downloaded yet
+    pub fn load(
+        file: PathBuf,
+        format: Option<Format>,
+        detail: Detail,
+        progress: Option<Arc<Progress>>,
+    ) -> Result<Self, failure::Error> {
+        let format = decoder::detect(&file)?
+            .or(format)
+            .ok_or_else(|| err_msg("unknown audio format"))?;
+        // frames of mp3 are counted, too few while downloading
+        let partial = format == Format::Mp3
+            && progress.as_ref().is_some_and(|progress| !progress.is_finished());
+        let header = if partial {
+            None
+        } else {
+            decoder::duration(&file, format)
+        };
+        let duration = header
+            .or(detail.duration)
+            .or_else(|| decoder::duration(&file, format))
+            .ok_or_else(|| err_msg("unknown song duration"))?;
         Ok(Self {
             duration,
             file,
-            status: Status::Stopped(::std::time::Duration::from_nanos(0)),
+            format,
+            detail,
+            gain: None,
+            progress,
+            clock: Arc::new(Clock::default()),
         })
     }
 }
@@ -93,3 +235,26 @@ impl AsRef<Path> for Track {
         &self.file
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn test_clock_counts_played_samples() {
+        let clock = Arc::new(Clock::default());
+        let samples = || SamplesBuffer::new(2, 1000, vec![0i16; 4000]);
+        let mut source = clock.count(samples(), Duration::from_millis(0));
+        source.by_ref().take(1000).count();
+        assert_eq!(clock.position(), Duration::from_millis(500));
+
+        // seek starts a new source, the old one doesn't move the clock
+        let seeked = clock.count(samples(), Duration::from_secs(10));
+        assert_eq!(clock.position(), Duration::from_secs(10));
+        source.count();
+        assert_eq!(clock.position(), Duration::from_secs(10));
+        seeked.count();
+        assert_eq!(clock.position(), Duration::from_secs(12));
+    }
+}
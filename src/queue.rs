@@ -0,0 +1,382 @@
+use super::model::playlist::Track;
+use rand::seq::SliceRandom;
+use std::collections::VecDeque;
+use std::fmt;
+
+// where a track of queue came from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    // name of playlist or track table
+    Playlist(String),
+    Album(String),
+    Fm,
+    Search,
+    // name of dj radio
+    DjProgram(String),
+    // name of song they are similar to
+    Similar(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Playlist(name) => write!(f, "Playlist: {}", name),
+            Source::Album(name) => write!(f, "Album: {}", name),
+            Source::Fm => write!(f, "Personal FM"),
+            Source::Search => write!(f, "Search"),
+            Source::DjProgram(name) => write!(f, "Dj program: {}", name),
+            Source::Similar(name) => write!(f, "Similar to: {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub track: Track,
+    pub source: Source,
+}
+
+// shuffled play order of queue indexes
+#[derive(Clone, Debug)]
+struct Shuffle {
+    // played tracks, the last one is current
+    played: Vec<usize>,
+    upcoming: VecDeque<usize>,
+}
+
+impl Shuffle {
+    fn new(len: usize, current: Option<usize>) -> Shuffle {
+        let mut shuffle = Shuffle {
+            played: current.into_iter().collect(),
+            upcoming: VecDeque::new(),
+        };
+        shuffle.refill(len);
+        // playing track is played in this round
+        shuffle.upcoming.retain(|i| Some(*i) != current);
+        shuffle
+    }
+
+    // a new permutation of all tracks, playing one isn't the first when there are others
+    fn refill(&mut self, len: usize) {
+        let mut order = (0..len).collect::<Vec<usize>>();
+        order.shuffle(&mut rand::thread_rng());
+        if len > 1 && order.first() == self.played.last() {
+            order.swap(0, len - 1);
+        }
+        self.upcoming = order.into();
+    }
+
+    fn next(&mut self, len: usize) -> Option<usize> {
+        if self.upcoming.is_empty() {
+            self.refill(len);
+        }
+        self.upcoming.front().copied()
+    }
+
+    fn previous(&self) -> Option<usize> {
+        self.played.iter().rev().nth(1).copied()
+    }
+
+    // walk forward, back, or jump to a track out of order
+    fn play(&mut self, index: usize) {
+        if self.upcoming.front() == Some(&index) {
+            self.upcoming.pop_front();
+            self.played.push(index);
+        } else if self.previous() == Some(index) {
+            if let Some(current) = self.played.pop() {
+                self.upcoming.push_front(current);
+            }
+        } else if self.played.last() != Some(&index) {
+            self.upcoming.retain(|i| *i != index);
+            self.played.push(index);
+        }
+    }
+
+    // change indexes after an insert at index
+    fn insert(&mut self, index: usize) {
+        self.played
+            .iter_mut()
+            .chain(self.upcoming.iter_mut())
+            .filter(|i| **i >= index)
+            .for_each(|i| *i += 1);
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.played.retain(|i| *i != index);
+        self.upcoming.retain(|i| *i != index);
+        self.played
+            .iter_mut()
+            .chain(self.upcoming.iter_mut())
+            .filter(|i| **i > index)
+            .for_each(|i| *i -= 1);
+    }
+
+    // tracks are swapped in queue, keep their play order
+    fn swap(&mut self, a: usize, b: usize) {
+        for i in self.played.iter_mut().chain(self.upcoming.iter_mut()) {
+            if *i == a {
+                *i = b;
+            } else if *i == b {
+                *i = a;
+            }
+        }
+    }
+}
+
+// tracks to play, browsing other lists doesn't change it
+// played tracks stay before the current one
+#[derive(Clone, Debug, Default)]
+pub struct Queue {
+    pub items: Vec<Item>,
+    // index of playing track, the next one is played after it
+    // none before the first track, after the playing track is removed at the top
+    pub current: Option<usize>,
+    // selected row of queue block
+    pub selected_index: usize,
+    // none if not shuffled
+    shuffle: Option<Shuffle>,
+}
+
+impl Queue {
+    pub fn track(&self, index: usize) -> Option<&Track> {
+        self.items.get(index).map(|item| &item.track)
+    }
+
+    // play a list from index
+    pub fn replace(&mut self, tracks: Vec<Track>, index: usize, source: Source) {
+        self.items = Queue::items(tracks, source);
+        self.current = Some(index);
+        self.selected_index = index;
+        if self.shuffle.is_some() {
+            self.shuffle = Some(Shuffle::new(self.items.len(), self.current));
+        }
+    }
+
+    // shuffled order is made once and kept until all tracks are played
+    pub fn set_shuffle(&mut self, on: bool) {
+        self.shuffle = if on {
+            Some(Shuffle::new(self.items.len(), self.current))
+        } else {
+            None
+        };
+    }
+
+    // next track of shuffled order, a new order after all tracks are played
+    pub fn next_shuffled(&mut self) -> Option<usize> {
+        let len = self.items.len();
+        self.shuffle.as_mut().and_then(|shuffle| shuffle.next(len))
+    }
+
+    // track played before current one in shuffled order
+    pub fn previous_shuffled(&self) -> Option<usize> {
+        self.shuffle.as_ref().and_then(Shuffle::previous)
+    }
+
+    pub fn set_current(&mut self, index: usize) {
+        self.current = Some(index);
+        if let Some(shuffle) = &mut self.shuffle {
+            shuffle.play(index);
+        }
+    }
+
+    fn items(tracks: Vec<Track>, source: Source) -> Vec<Item> {
+        tracks
+            .into_iter()
+            .map(|track| Item {
+                track,
+                source: source.to_owned(),
+            })
+            .collect()
+    }
+
+    // where a track played next goes
+    fn next_place(&self) -> usize {
+        self.current
+            .map_or(0, |current| current + 1)
+            .min(self.items.len())
+    }
+
+    // put track after the playing one and make it current
+    pub fn play_now(&mut self, track: Track, source: Source) {
+        let index = self.insert(track, source);
+        self.set_current(index);
+    }
+
+    pub fn play_next(&mut self, track: Track, source: Source) {
+        let index = self.insert(track, source);
+        if let Some(shuffle) = &mut self.shuffle {
+            shuffle.upcoming.push_front(index);
+        }
+    }
+
+    fn insert(&mut self, track: Track, source: Source) -> usize {
+        let index = self.next_place();
+        self.items.insert(index, Item { track, source });
+        if let Some(shuffle) = &mut self.shuffle {
+            shuffle.insert(index);
+        }
+        index
+    }
+
+    pub fn append(&mut self, tracks: Vec<Track>, source: Source) {
+        let start = self.items.len();
+        self.items.extend(Queue::items(tracks, source));
+        if let Some(shuffle) = &mut self.shuffle {
+            // played after the tracks of this round
+            if !shuffle.upcoming.is_empty() {
+                shuffle.upcoming.extend(start..self.items.len());
+            }
+        }
+    }
+
+    // playing track keeps playing when removed, the track after it is next
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.items.len() {
+            return;
+        }
+        self.items.remove(index);
+        if let Some(shuffle) = &mut self.shuffle {
+            shuffle.remove(index);
+        }
+        self.current = match self.current {
+            Some(current) if index <= current => current.checked_sub(1),
+            current => current,
+        };
+        self.selected_index = self.selected_index.min(self.items.len().saturating_sub(1));
+    }
+
+    // swap track with the one above, selection follows it
+    pub fn move_up(&mut self, index: usize) {
+        if index == 0 || index >= self.items.len() {
+            return;
+        }
+        self.swap(index - 1, index);
+        self.selected_index = index - 1;
+    }
+
+    // swap track with the one below, selection follows it
+    pub fn move_down(&mut self, index: usize) {
+        if index + 1 >= self.items.len() {
+            return;
+        }
+        self.swap(index, index + 1);
+        self.selected_index = index + 1;
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.items.swap(a, b);
+        if let Some(shuffle) = &mut self.shuffle {
+            shuffle.swap(a, b);
+        }
+        self.current = match self.current {
+            Some(current) if current == a => Some(b),
+            Some(current) if current == b => Some(a),
+            current => current,
+        };
+    }
+
+    // keep the playing track only
+    pub fn clear_rest(&mut self) {
+        match self.current.filter(|current| *current < self.items.len()) {
+            Some(current) => {
+                let item = self.items.swap_remove(current);
+                self.items = vec![item];
+                self.current = Some(0);
+            }
+            None => {
+                self.items.clear();
+                self.current = None;
+            }
+        }
+        self.selected_index = 0;
+        if self.shuffle.is_some() {
+            self.shuffle = Some(Shuffle::new(self.items.len(), self.current));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: i64) -> Track {
+        Track {
+            name: None,
+            id: Some(id),
+            artists: None,
+            album: None,
+            no: None,
+            duration: None,
+            radio: None,
+        }
+    }
+
+    fn ids(queue: &Queue) -> Vec<i64> {
+        queue.items.iter().filter_map(|item| item.track.id).collect()
+    }
+
+    #[test]
+    fn test_queue_keeps_playing_track() {
+        let mut queue = Queue::default();
+        queue.play_next(track(1), Source::Search);
+        queue.append(vec![track(2), track(3)], Source::Fm);
+        assert_eq!(queue.current, None);
+        queue.play_now(track(4), Source::Search);
+        assert_eq!((ids(&queue), queue.current), (vec![4, 1, 2, 3], Some(0)));
+        queue.play_next(track(5), Source::Album("a".to_owned()));
+        assert_eq!(ids(&queue), vec![4, 5, 1, 2, 3]);
+        assert_eq!(queue.items[1].source, Source::Album("a".to_owned()));
+        queue.move_down(0);
+        assert_eq!((ids(&queue), queue.current), (vec![5, 4, 1, 2, 3], Some(1)));
+        queue.move_up(4);
+        assert_eq!((ids(&queue), queue.selected_index), (vec![5, 4, 1, 3, 2], 3));
+        queue.remove(0);
+        assert_eq!(queue.current, Some(0));
+        // track after the removed playing one is next
+        queue.remove(0);
+        assert_eq!((ids(&queue), queue.current), (vec![1, 3, 2], None));
+    }
+
+    #[test]
+    fn test_shuffle_walks_both_ways() {
+        let mut queue = Queue::default();
+        queue.replace((0..5).map(track).collect(), 2, Source::Search);
+        queue.set_shuffle(true);
+        let mut order = vec![2];
+        for _ in 0..4 {
+            let index = queue.next_shuffled().unwrap();
+            queue.set_current(index);
+            order.push(index);
+        }
+        // every track is played once in a round
+        let mut sorted = order.to_owned();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+        let previous = queue.previous_shuffled().unwrap();
+        assert_eq!(previous, order[3]);
+        queue.set_current(previous);
+        assert_eq!(queue.next_shuffled(), Some(order[4]));
+        queue.set_current(order[4]);
+        // new round after all are played, not starting with the last one
+        let next = queue.next_shuffled().unwrap();
+        assert_ne!(next, order[4]);
+        queue.set_current(next);
+        assert_eq!(queue.previous_shuffled(), Some(order[4]));
+
+        queue.play_next(track(5), Source::Fm);
+        let index = queue.next_shuffled().unwrap();
+        assert_eq!(queue.track(index).and_then(|track| track.id), Some(5));
+    }
+
+    #[test]
+    fn test_clear_rest_of_queue() {
+        let mut queue = Queue::default();
+        queue.replace(vec![track(1), track(2), track(3)], 1, Source::Fm);
+        queue.clear_rest();
+        assert_eq!((ids(&queue), queue.current), (vec![2], Some(0)));
+        queue.remove(0);
+        queue.append(vec![track(4)], Source::Search);
+        queue.clear_rest();
+        assert!(queue.items.is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use super::api::CloudMusic;
+use super::model::lyric::Lyric;
+use super::model::playlist::Track;
+use super::model::song::Song;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+
+// what the url of song is asked for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UrlFor {
+    Play,
+    Preload,
+}
+
+// api requests which would hold the ui
+pub enum Request {
+    // id and bitrate of song
+    SongUrl(i64, u32, UrlFor),
+    // id of song
+    Lyric(i64),
+    // id of song to find similar songs of
+    Similar(i64),
+}
+
+// results send from resolver thread
+pub enum Resolved {
+    SongUrl(i64, u32, UrlFor, Result<Song, failure::Error>),
+    Lyric(i64, Result<Vec<Lyric>, failure::Error>),
+    Similar(i64, Result<Vec<Track>, failure::Error>),
+}
+
+// handle of resolver thread, requests are done in order
+pub struct Resolver {
+    requests: Sender<Request>,
+    events: Receiver<Resolved>,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        let (requests, request_rx) = mpsc::channel();
+        let (events_tx, events) = mpsc::channel();
+        thread::spawn(move || {
+            let api = CloudMusic::default();
+            for request in request_rx {
+                if events_tx.send(Resolver::resolve(&api, request)).is_err() {
+                    break;
+                }
+            }
+        });
+        Resolver { requests, events }
+    }
+
+    // requests are kept in the returned receiver instead of asking api
+    #[cfg(test)]
+    pub fn offline() -> (Resolver, Receiver<Request>) {
+        let (requests, request_rx) = mpsc::channel();
+        let (_, events) = mpsc::channel();
+        (Resolver { requests, events }, request_rx)
+    }
+
+    fn resolve(api: &CloudMusic, request: Request) -> Resolved {
+        match request {
+            Request::SongUrl(id, br, url_for) => {
+                Resolved::SongUrl(id, br, url_for, api.get_song_url(&id.to_string(), br))
+            }
+            Request::Lyric(id) => Resolved::Lyric(id, api.lyric(&id.to_string())),
+            Request::Similar(id) => Resolved::Similar(id, api.simi_songs(&id.to_string())),
+        }
+    }
+
+    pub fn request(&self, request: Request) {
+        if let Err(e) = self.requests.send(request) {
+            error!("send resolver request failed {}", e);
+        }
+    }
+
+    pub fn poll_events(&self) -> Vec<Resolved> {
+        self.events.try_iter().collect()
+    }
+}
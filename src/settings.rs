@@ -0,0 +1,197 @@
+use super::alarm::Alarm;
+use chrono::Local;
+use config::Config;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "netease-music-tui";
+
+// quality of song url
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Quality {
+    Standard,
+    Higher,
+    Exhaustive,
+    Lossless,
+}
+
+impl Quality {
+    pub fn new(name: &str) -> Option<Quality> {
+        match name.to_lowercase().as_str() {
+            "standard" => Some(Quality::Standard),
+            "higher" => Some(Quality::Higher),
+            "exhaustive" => Some(Quality::Exhaustive),
+            "lossless" => Some(Quality::Lossless),
+            _ => None,
+        }
+    }
+
+    // bitrate asked from song url api, lossless gets flac
+    pub fn bitrate(self) -> u32 {
+        match self {
+            Quality::Standard => 128_000,
+            Quality::Higher => 192_000,
+            Quality::Exhaustive => 320_000,
+            Quality::Lossless => 999_000,
+        }
+    }
+
+    pub fn next(self) -> Quality {
+        match self {
+            Quality::Standard => Quality::Higher,
+            Quality::Higher => Quality::Exhaustive,
+            Quality::Exhaustive => Quality::Lossless,
+            Quality::Lossless => Quality::Standard,
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Quality::Standard => "standard",
+            Quality::Higher => "higher",
+            Quality::Exhaustive => "exhaustive",
+            Quality::Lossless => "lossless",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// loudness normalisation of tracks
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Normalisation {
+    Off,
+    // every track plays at the same loudness
+    Track,
+    // tracks keep their loudness difference in an album
+    Album,
+}
+
+impl Normalisation {
+    pub fn new(name: &str) -> Option<Normalisation> {
+        match name.to_lowercase().as_str() {
+            "off" => Some(Normalisation::Off),
+            "track" => Some(Normalisation::Track),
+            "album" => Some(Normalisation::Album),
+            _ => None,
+        }
+    }
+}
+
+// settings of Settings.toml besides the account
+#[derive(Clone, Debug)]
+pub struct Settings {
+    // seconds of crossfade between tracks, 0 means no crossfade
+    pub crossfade: u64,
+    // directory of cached songs
+    pub cache_dir: PathBuf,
+    // max size of cache dir in MB, 0 means no cache
+    pub cache_size: u64,
+    // directory of downloaded songs for offline playing
+    pub music_dir: PathBuf,
+    // path of downloaded song in music dir
+    // {artist}, {album}, {no} and {title} are replaced by track info
+    pub file_template: String,
+    // max count of songs downloading at the same time
+    pub max_downloads: usize,
+    pub quality: Quality,
+    pub normalisation: Normalisation,
+    // dB added to the normalised gain
+    pub pre_amp: f32,
+    // audio output, rodio, pipe:<path>, wav:<path> or null
+    pub backend: String,
+    // name of sound card device, default device if not set
+    pub device: Option<String>,
+    // seconds of volume fade out before sleep timer pauses
+    pub sleep_fade: u64,
+    pub alarms: Vec<Alarm>,
+    // seconds of volume ramping up after alarm starts playing
+    pub alarm_ramp: u64,
+    // play similar songs after the last track when repeat is off
+    pub play_similar: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            crossfade: 0,
+            cache_dir: dirs::cache_dir()
+                .unwrap_or_else(env::temp_dir)
+                .join(APP_DIR),
+            cache_size: 1024,
+            music_dir: dirs::audio_dir()
+                .or_else(|| dirs::home_dir().map(|home| home.join("Music")))
+                .unwrap_or_else(env::temp_dir)
+                .join(APP_DIR),
+            file_template: "{artist}/{album}/{no} - {title}.mp3".to_owned(),
+            max_downloads: 3,
+            quality: Quality::Exhaustive,
+            normalisation: Normalisation::Off,
+            pre_amp: 0.0,
+            backend: "rodio".to_owned(),
+            device: None,
+            sleep_fade: 30,
+            alarms: vec![],
+            alarm_ramp: 60,
+            play_similar: false,
+        }
+    }
+}
+
+impl Settings {
+    // read settings from config, use default if not set
+    pub fn new(config: &Config) -> Settings {
+        let default = Settings::default();
+        Settings {
+            crossfade: config
+                .get::<u64>("crossfade")
+                .unwrap_or(default.crossfade),
+            cache_dir: config
+                .get::<String>("cache_dir")
+                .map(PathBuf::from)
+                .unwrap_or(default.cache_dir),
+            cache_size: config
+                .get::<u64>("cache_size")
+                .unwrap_or(default.cache_size),
+            music_dir: config
+                .get::<String>("music_dir")
+                .map(PathBuf::from)
+                .unwrap_or(default.music_dir),
+            file_template: config
+                .get::<String>("file_template")
+                .unwrap_or(default.file_template),
+            max_downloads: config
+                .get::<usize>("max_downloads")
+                .unwrap_or(default.max_downloads),
+            quality: config
+                .get::<String>("quality")
+                .ok()
+                .and_then(|quality| Quality::new(&quality))
+                .unwrap_or(default.quality),
+            normalisation: config
+                .get::<String>("normalisation")
+                .ok()
+                .and_then(|normalisation| Normalisation::new(&normalisation))
+                .unwrap_or(default.normalisation),
+            pre_amp: config
+                .get::<f32>("pre_amp")
+                .unwrap_or(default.pre_amp),
+            backend: config
+                .get::<String>("backend")
+                .unwrap_or(default.backend),
+            device: config.get::<String>("device").ok(),
+            sleep_fade: config
+                .get::<u64>("sleep_fade")
+                .unwrap_or(default.sleep_fade),
+            alarms: Alarm::from_config(config, Local::now().naive_local()),
+            alarm_ramp: config
+                .get::<u64>("alarm_ramp")
+                .unwrap_or(default.alarm_ramp),
+            play_similar: config
+                .get::<bool>("play_similar")
+                .unwrap_or(default.play_similar),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use failure::err_msg;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+// minutes of sleep timer key presets
+const MINUTES: [u64; 5] = [15, 30, 45, 60, 90];
+// track counts of sleep timer key presets
+const TRACKS: [u32; 4] = [1, 2, 3, 5];
+
+// when the sleep timer pauses playback
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sleep {
+    // time from now
+    After(Duration),
+    // end of tracks, the current one is the first
+    Tracks(u32),
+}
+
+impl Sleep {
+    // "30m", "90s", "1h", "track", "3 tracks" or minutes, none for "off"
+    // it's set by mpris
+    #[allow(unused)]
+    pub fn parse(text: &str) -> Result<Option<Sleep>, failure::Error> {
+        let text = text.trim().to_lowercase();
+        if text == "off" {
+            return Ok(None);
+        }
+        if text == "track" {
+            return Ok(Some(Sleep::Tracks(1)));
+        }
+        let index = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let count = text[..index]
+            .parse::<u64>()
+            .map_err(|_| format_err!("invalid sleep timer {}", text))?;
+        let too_long = || format_err!("sleep timer {} is too long", text);
+        let secs = |unit: u64| count.checked_mul(unit).ok_or_else(too_long);
+        let sleep = match text[index..].trim() {
+            "" | "m" | "min" => Sleep::After(Duration::from_secs(secs(60)?)),
+            "s" => Sleep::After(Duration::from_secs(count)),
+            "h" => Sleep::After(Duration::from_secs(secs(3600)?)),
+            "track" | "tracks" => Sleep::Tracks(u32::try_from(count).map_err(|_| too_long())?),
+            _ => return Err(format_err!("invalid sleep timer {}", text)),
+        };
+        // deadline of timer must fit in an instant
+        if let Sleep::After(duration) = sleep {
+            Instant::now().checked_add(duration).ok_or_else(too_long)?;
+        }
+        match sleep {
+            Sleep::Tracks(0) => Err(err_msg("sleep timer needs one track at least")),
+            sleep => Ok(Some(sleep)),
+        }
+    }
+
+    // next key preset of minutes or tracks, none after the last one
+    pub fn next(current: Option<Sleep>, tracks: bool) -> Option<Sleep> {
+        if tracks {
+            let index = match current {
+                Some(Sleep::Tracks(count)) => TRACKS.iter().position(|c| *c > count),
+                _ => Some(0),
+            };
+            index.map(|index| Sleep::Tracks(TRACKS[index]))
+        } else {
+            let index = match current {
+                Some(Sleep::After(duration)) => {
+                    MINUTES.iter().position(|m| m * 60 > duration.as_secs())
+                }
+                _ => Some(0),
+            };
+            index.map(|index| Sleep::After(Duration::from_secs(MINUTES[index] * 60)))
+        }
+    }
+}
+
+enum Until {
+    Time(Instant),
+    Tracks(u32),
+}
+
+// fade volume down before sleep and pause
+pub struct SleepTimer {
+    sleep: Sleep,
+    until: Until,
+    fade: Duration,
+}
+
+impl SleepTimer {
+    pub fn new(sleep: Sleep, fade: Duration) -> SleepTimer {
+        let until = match sleep {
+            Sleep::After(duration) => Until::Time(Instant::now() + duration),
+            Sleep::Tracks(count) => Until::Tracks(count),
+        };
+        SleepTimer { sleep, until, fade }
+    }
+
+    // how the timer was set
+    pub fn sleep(&self) -> Sleep {
+        self.sleep
+    }
+
+    // tracks left to play, none for timer of duration
+    pub fn tracks(&self) -> Option<u32> {
+        match self.until {
+            Until::Tracks(count) => Some(count),
+            Until::Time(_) => None,
+        }
+    }
+
+    // time left before sleep, track_left is the real time left of current track
+    // none if more tracks are left than the current one
+    pub fn remaining(&self, track_left: Option<Duration>) -> Option<Duration> {
+        match self.until {
+            Until::Time(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+            Until::Tracks(1) => track_left,
+            Until::Tracks(_) => None,
+        }
+    }
+
+    // timer of duration runs out, timer of tracks waits for end of track
+    pub fn is_due(&self) -> bool {
+        match self.until {
+            Until::Time(deadline) => Instant::now() >= deadline,
+            Until::Tracks(_) => false,
+        }
+    }
+
+    // volume factor of fade out
+    pub fn volume(&self, remaining: Option<Duration>) -> f32 {
+        match remaining {
+            Some(remaining) if remaining < self.fade => {
+                remaining.as_secs_f32() / self.fade.as_secs_f32()
+            }
+            _ => 1.0,
+        }
+    }
+
+    // a track is finished, true if it was the last one
+    pub fn track_finished(&mut self) -> bool {
+        match &mut self.until {
+            Until::Tracks(count) => {
+                *count = count.saturating_sub(1);
+                *count == 0
+            }
+            Until::Time(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sleep() {
+        assert_eq!(
+            Sleep::parse("30m").unwrap(),
+            Some(Sleep::After(Duration::from_secs(1800)))
+        );
+        assert_eq!(
+            Sleep::parse("45").unwrap(),
+            Some(Sleep::After(Duration::from_secs(2700)))
+        );
+        assert_eq!(Sleep::parse("track").unwrap(), Some(Sleep::Tracks(1)));
+        assert_eq!(Sleep::parse("3 tracks").unwrap(), Some(Sleep::Tracks(3)));
+        assert_eq!(Sleep::parse("off").unwrap(), None);
+        assert!(Sleep::parse("soon").is_err());
+        assert!(Sleep::parse("0 tracks").is_err());
+        assert!(Sleep::parse("307445734561825861h").is_err());
+        assert!(Sleep::parse("4294967296 tracks").is_err());
+        assert_eq!(Sleep::next(Some(Sleep::Tracks(3)), true), Some(Sleep::Tracks(5)));
+        assert_eq!(Sleep::next(Some(Sleep::Tracks(5)), true), None);
+        assert_eq!(
+            Sleep::next(Some(Sleep::Tracks(5)), false),
+            Some(Sleep::After(Duration::from_secs(900)))
+        );
+    }
+
+    #[test]
+    fn test_fade_before_sleep() {
+        let mut timer = SleepTimer::new(Sleep::Tracks(2), Duration::from_secs(10));
+        let left = Some(Duration::from_secs(5));
+        // fade on the last track only
+        assert_eq!(timer.volume(timer.remaining(left)), 1.0);
+        assert!(!timer.track_finished());
+        assert_eq!(timer.volume(timer.remaining(left)), 0.5);
+        assert!(timer.track_finished());
+
+        let timer = SleepTimer::new(Sleep::After(Duration::from_secs(0)), Duration::from_secs(0));
+        assert!(timer.is_due());
+        assert_eq!(timer.volume(timer.remaining(None)), 1.0);
+    }
+}
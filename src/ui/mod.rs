@@ -1,6 +1,7 @@
 pub mod circle;
 mod util;
 
+use super::alarm::Wake;
 use super::app::{ActiveBlock, App, RepeatState, RouteId, RECOMMEND_OPTIONS};
 use tui::backend::Backend;
 use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
@@ -10,9 +11,10 @@ use tui::widgets::{
     Widget,
 };
 use tui::Frame;
+use super::download::DownloadStatus;
 use util::{
-    create_artist_string, create_datetime_string, create_tag_string, display_track_progress,
-    get_color, get_percentage_width,
+    create_artist_string, create_datetime_string, create_tag_string, display_download_progress,
+    display_track_progress, get_color, get_percentage_width, millis_to_minutes,
 };
 
 // table item for render
@@ -154,6 +156,18 @@ where
         RouteId::DjProgram => {
             draw_dj_program_list(f, app, chunks[1]);
         }
+        RouteId::Downloads => {
+            draw_downloads(f, app, chunks[1]);
+        }
+        RouteId::Devices => {
+            draw_devices(f, app, chunks[1]);
+        }
+        RouteId::Alarms => {
+            draw_alarms(f, app, chunks[1]);
+        }
+        RouteId::Queue => {
+            draw_queue(f, app, chunks[1]);
+        }
         RouteId::Playing => {
             draw_playing_detail(f, app, chunks[1]);
         }
@@ -179,7 +193,9 @@ where
         .margin(1)
         .split(layout_chunk);
 
-    let state_title = if app.player.is_playing() {
+    let state_title = if app.player.is_loading() {
+        "Loading"
+    } else if app.player.is_playing() {
         "Playing"
     } else {
         "Pause "
@@ -188,6 +204,7 @@ where
     let repeat_text = match app.fm_state {
         true => "FM",
         false => match app.repeat_state {
+            RepeatState::Off if app.play_similar => "Off, then similar",
             RepeatState::Off => "Off",
             RepeatState::Track => "Track",
             RepeatState::All => "All",
@@ -195,7 +212,53 @@ where
         },
     };
 
-    let title = format!("{} | Repeat: {}", state_title, repeat_text);
+    // bitrate and format of playing song, it's unknown for cached song of last session
+    let quality_text = match &app.current_playing {
+        Some(track) => match track.id {
+            Some(id) if app.downloader.file(id).is_some() => "local".to_owned(),
+            Some(id) => match app.song_info.get(&id) {
+                Some(song) => format!(
+                    "{}kbps {}",
+                    song.br.unwrap_or(0) / 1000,
+                    song.format.to_owned().unwrap_or_default()
+                ),
+                None => app.quality.to_string(),
+            },
+            None => app.quality.to_string(),
+        },
+        None => app.quality.to_string(),
+    };
+
+    // speed of dj program
+    let speed = app.player.get_speed();
+    let repeat_text = if speed != 1.0 {
+        format!("{} | Speed: {}x", repeat_text, speed)
+    } else {
+        repeat_text.to_owned()
+    };
+
+    // time or tracks left before sleep timer pauses
+    let sleep_text = match &app.sleep {
+        Some(timer) => match timer.tracks() {
+            Some(1) => " | Sleep: after track".to_owned(),
+            Some(tracks) => format!(" | Sleep: after {} tracks", tracks),
+            None => format!(
+                " | Sleep: {}",
+                millis_to_minutes(
+                    timer
+                        .remaining(None)
+                        .unwrap_or_default()
+                        .as_millis() as u64
+                )
+            ),
+        },
+        None => String::new(),
+    };
+
+    let title = format!(
+        "{} | Repeat: {} | Quality: {}{}",
+        state_title, repeat_text, quality_text, sleep_text
+    );
 
     let current_route = app.get_current_route();
     let highlight_state = (
@@ -239,6 +302,11 @@ where
         ),
         None => (0.0_f64, " ".to_string()),
     };
+    // track is played while downloading
+    let label = match app.player.get_buffered() {
+        Some(buffered) => format!("{} | Buffered: {}%", label, buffered),
+        None => label,
+    };
 
     Gauge::default()
         .block(Block::default().title(""))
@@ -381,7 +449,7 @@ where
             TableItem {
                 id: item.id.as_ref().unwrap().to_string(),
                 format: vec![
-                    num.to_string(),
+                    track_number(app, num, item.id),
                     item.name.as_ref().unwrap().to_string(),
                     create_artist_string(&item.artists.to_owned().unwrap()),
                     item.album.to_owned().unwrap().name.unwrap(),
@@ -402,6 +470,92 @@ where
     )
 }
 
+pub fn draw_queue<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let header = [
+        TableHeader {
+            text: "ID",
+            width: get_percentage_width(layout_chunk.width, 0.05),
+        },
+        TableHeader {
+            text: "Title",
+            width: get_percentage_width(layout_chunk.width, 0.25),
+        },
+        TableHeader {
+            text: "Artist",
+            width: get_percentage_width(layout_chunk.width, 0.2),
+        },
+        TableHeader {
+            text: "Album",
+            width: get_percentage_width(layout_chunk.width, 0.2),
+        },
+        TableHeader {
+            text: "From",
+            width: get_percentage_width(layout_chunk.width, 0.25),
+        },
+    ];
+
+    let current_route = app.get_current_route();
+    let highlight_state = (
+        current_route.active_block == ActiveBlock::Queue,
+        current_route.hovered_block == ActiveBlock::Queue,
+    );
+
+    // dj programs have no album
+    let items = app
+        .queue
+        .items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let track = &item.track;
+            TableItem {
+                id: track.id.unwrap_or_default().to_string(),
+                format: vec![
+                    track_number(app, i + 1, track.id),
+                    track.name.to_owned().unwrap_or_default(),
+                    create_artist_string(&track.artists.to_owned().unwrap_or_default()),
+                    track
+                        .album
+                        .as_ref()
+                        .and_then(|album| album.name.to_owned())
+                        .unwrap_or_default(),
+                    item.source.to_string(),
+                ],
+            }
+        })
+        .collect::<Vec<TableItem>>();
+
+    // played tracks stay above the playing one
+    let title = match app.queue.current {
+        Some(current) => format!(
+            "Play Queue ({} played, {} up next)",
+            current,
+            app.queue.items.len().saturating_sub(current + 1)
+        ),
+        None => format!("Play Queue ({} up next)", app.queue.items.len()),
+    };
+    draw_table(
+        f,
+        app,
+        layout_chunk,
+        (&title, &header),
+        &items,
+        app.queue.selected_index,
+        highlight_state,
+    )
+}
+
+// number column of track, downloaded track is marked
+fn track_number(app: &App, num: usize, id: Option<i64>) -> String {
+    match id {
+        Some(id) if app.downloader.is_downloaded(id) => format!("{} ✓", num),
+        _ => num.to_string(),
+    }
+}
+
 fn draw_table<B>(
     f: &mut Frame<B>,
     app: &App,
@@ -720,7 +874,13 @@ where
         vec!["Skip to previous track", "p", "General"],
         vec!["Seek forwards", ">", "General"],
         vec!["Seek backwards", "<", "General"],
+        vec!["Speed up dj program", "]", "General"],
+        vec!["Slow down dj program", "[", "General"],
+        vec!["Sleep timer by minutes", "z", "General"],
+        vec!["Sleep timer by tracks", "Z", "General"],
         vec!["Toggle repeat mode", "r", "General"],
+        vec!["Toggle quality of next tracks", "b", "General"],
+        vec!["Toggle similar songs after the end of queue", "A", "General"],
         vec!["Move selection left", "h | <Left Arrow Key> ", "General"],
         vec!["Move selection down", "j | <Down Arrow Key> ", "General"],
         vec!["Move selection up", "k | <Up Arrow Key> ", "General"],
@@ -739,6 +899,23 @@ where
         vec!["Like current playing track", "<Ctrl+y>", "General"],
         vec!["Dislike current playing track", "<Ctrl+d>", "General"],
         vec!["move track to trash", "<Ctrl+t>", "FM block"],
+        vec![
+            "Download tracks for offline playing",
+            "D",
+            "Track table | album | playlist",
+        ],
+        vec!["Pause/Resume download", "<Enter>", "Downloads"],
+        vec!["Cancel download", "c", "Downloads"],
+        vec!["Retry failed download", "R", "Downloads"],
+        vec!["Play to selected device", "<Enter>", "Devices"],
+        vec!["Turn selected alarm on/off", "<Enter>", "Alarms"],
+        vec!["Play track now", "P", "Track table | album | search result | dj program"],
+        vec!["Play track next", "N", "Track table | album | search result | dj program"],
+        vec!["Add track to queue", "e", "Track table | album | search result | dj program"],
+        vec!["Play selected track", "<Enter>", "Play queue"],
+        vec!["Remove track from queue", "x", "Play queue"],
+        vec!["Move track up/down", "K/J", "Play queue"],
+        vec!["Clear queue but the playing track", "C", "Play queue"],
         vec!["Delete entire input", "<Ctrl+u>", "Search input"],
         vec!["Search with input text", "<Enter>", "Search input"],
         vec!["Jump to start of input", "<Ctrl+a>", "Search input"],
@@ -966,7 +1143,10 @@ where
                     num += 1;
                     TableItem {
                         id: item.id.clone().unwrap_or_else(|| 0).to_string(),
-                        format: vec![num.to_string(), item.to_owned().name.unwrap().to_string()],
+                        format: vec![
+                            track_number(app, num, item.id),
+                            item.to_owned().name.unwrap().to_string(),
+                        ],
                     }
                 })
                 .collect::<Vec<TableItem>>(),
@@ -1330,6 +1510,194 @@ where
     };
 }
 
+pub fn draw_downloads<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let current_route = app.get_current_route();
+    let highlight_state = (
+        current_route.active_block == ActiveBlock::Downloads,
+        current_route.hovered_block == ActiveBlock::Downloads,
+    );
+
+    let header = [
+        TableHeader {
+            text: "",
+            width: get_percentage_width(layout_chunk.width, 0.05),
+        },
+        TableHeader {
+            text: "Name",
+            width: get_percentage_width(layout_chunk.width, 0.45),
+        },
+        TableHeader {
+            text: "Status",
+            width: get_percentage_width(layout_chunk.width, 0.25),
+        },
+        TableHeader {
+            text: "Progress",
+            width: get_percentage_width(layout_chunk.width, 0.2),
+        },
+    ];
+
+    let mut num = 0;
+    let items = app
+        .downloader
+        .items
+        .iter()
+        .map(|item| {
+            num += 1;
+            let status = match &item.status {
+                DownloadStatus::Queued => "queued".to_owned(),
+                DownloadStatus::Active => "downloading".to_owned(),
+                DownloadStatus::Paused => "paused".to_owned(),
+                DownloadStatus::Finished => "finished".to_owned(),
+                DownloadStatus::Failed(e) => format!("failed: {}", e),
+            };
+            TableItem {
+                id: item.id.to_string(),
+                format: vec![
+                    num.to_string(),
+                    item.name.to_owned(),
+                    status,
+                    display_download_progress(item.downloaded(), item.total()),
+                ],
+            }
+        })
+        .collect::<Vec<TableItem>>();
+
+    draw_table(
+        f,
+        app,
+        layout_chunk,
+        ("Downloads", &header),
+        &items,
+        app.downloader.selected_index,
+        highlight_state,
+    )
+}
+
+pub fn draw_devices<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let current_route = app.get_current_route();
+    let highlight_state = (
+        current_route.active_block == ActiveBlock::Devices,
+        current_route.hovered_block == ActiveBlock::Devices,
+    );
+
+    let header = [
+        TableHeader {
+            text: "",
+            width: get_percentage_width(layout_chunk.width, 0.05),
+        },
+        TableHeader {
+            text: "Device",
+            width: get_percentage_width(layout_chunk.width, 0.9),
+        },
+    ];
+
+    let items = app
+        .device_table
+        .devices
+        .iter()
+        .map(|device| {
+            // mark the playing device
+            let mark = if app.player.device() == Some(device.as_str()) {
+                "✓"
+            } else {
+                ""
+            };
+            TableItem {
+                id: device.to_owned(),
+                format: vec![mark.to_owned(), device.to_owned()],
+            }
+        })
+        .collect::<Vec<TableItem>>();
+
+    draw_table(
+        f,
+        app,
+        layout_chunk,
+        ("Devices", &header),
+        &items,
+        app.device_table.selected_index,
+        highlight_state,
+    )
+}
+
+pub fn draw_alarms<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let current_route = app.get_current_route();
+    let highlight_state = (
+        current_route.active_block == ActiveBlock::Alarms,
+        current_route.hovered_block == ActiveBlock::Alarms,
+    );
+
+    let header = [
+        TableHeader {
+            text: "Time",
+            width: get_percentage_width(layout_chunk.width, 0.15),
+        },
+        TableHeader {
+            text: "Playlist",
+            width: get_percentage_width(layout_chunk.width, 0.45),
+        },
+        TableHeader {
+            text: "Next",
+            width: get_percentage_width(layout_chunk.width, 0.25),
+        },
+        TableHeader {
+            text: "On/Off",
+            width: get_percentage_width(layout_chunk.width, 0.1),
+        },
+    ];
+
+    let items = app
+        .alarm_table
+        .alarms
+        .iter()
+        .map(|alarm| {
+            let playlist = match alarm.wake {
+                // name of user playlist, or id of others
+                Wake::Playlist(id) => app
+                    .playlists
+                    .as_ref()
+                    .and_then(|playlists| playlists.iter().find(|p| p.id == Some(id)))
+                    .and_then(|playlist| playlist.name.to_owned())
+                    .unwrap_or_else(|| id.to_string()),
+                Wake::Fm => "Personal FM".to_owned(),
+            };
+            let (next, state) = if alarm.enabled {
+                (alarm.next().format("%a %H:%M").to_string(), "On")
+            } else {
+                (String::new(), "Off")
+            };
+            TableItem {
+                id: alarm.time.to_string(),
+                format: vec![
+                    alarm.time.format("%H:%M").to_string(),
+                    playlist,
+                    next,
+                    state.to_owned(),
+                ],
+            }
+        })
+        .collect::<Vec<TableItem>>();
+
+    draw_table(
+        f,
+        app,
+        layout_chunk,
+        ("Alarms", &header),
+        &items,
+        app.alarm_table.selected_index,
+        highlight_state,
+    )
+}
+
 pub fn draw_error_screen<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
 where
     B: Backend,
@@ -40,7 +40,7 @@ pub fn millis_to_minutes(millis: u64) -> String {
 pub fn display_track_progress(progress: u64, track_duration: u64) -> String {
     let duration = millis_to_minutes(u64::from(track_duration));
     let progress_display = millis_to_minutes(progress);
-    let remaining = millis_to_minutes(u64::from(track_duration) - progress);
+    let remaining = millis_to_minutes(track_duration.saturating_sub(progress));
 
     format!("{}/{} (-{})", progress_display, duration, remaining,)
 }
@@ -66,6 +66,20 @@ pub fn create_datetime_string(timestamp: u64) -> String {
     datetime.format("%Y-%m-%d").to_string()
 }
 
+// display downloaded bytes in MB and percentage if total size is known
+pub fn display_download_progress(downloaded: u64, total: Option<u64>) -> String {
+    let mb = |bytes: u64| bytes as f64 / 1024.0 / 1024.0;
+    match total {
+        Some(total) if total > 0 => format!(
+            "{:.1}/{:.1} MB ({}%)",
+            mb(downloaded),
+            mb(total),
+            downloaded * 100 / total
+        ),
+        _ => format!("{:.1} MB", mb(downloaded)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +88,13 @@ mod tests {
     fn test_create_datatime_string() {
         assert_eq!(create_datetime_string(1576472814620), "2019-12-16");
     }
+
+    #[test]
+    fn test_display_download_progress() {
+        assert_eq!(
+            display_download_progress(1048576, Some(4194304)),
+            "1.0/4.0 MB (25%)"
+        );
+        assert_eq!(display_download_progress(1572864, None), "1.5 MB");
+    }
 }